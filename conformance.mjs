@@ -0,0 +1,89 @@
+// conformance tests against the reference Nix evaluator:
+// evaluates `docs/testset/*.nix` via `nix-instantiate --eval --strict --json`
+// and via the translated code (using the pure runtime of the `eval` feature,
+// `src/eval_prelude.js`), and compares the results.
+// Fixtures without `# expected:` header, expected errors, or impure builtins are skipped.
+// Requires `nix-instantiate` in PATH, thus only runs if `NIX2JS_CONFORMANCE=1` is set.
+// USAGE: NIX2JS_CONFORMANCE=1 node conformance.mjs
+import { execFileSync } from 'node:child_process';
+import fs from 'node:fs/promises';
+import path from 'node:path';
+import vm from 'node:vm';
+import { fileURLToPath } from 'node:url';
+import { translate } from 'nix2js-wasm';
+
+const IMPURE = /\b(import|readFile|readDir|pathExists|getEnv|currentTime|currentSystem|storePath|toFile|fetch\w*|hashFile|hashString|placeholder)\b|<[\w.+-]+>|\.\.?\/[\w.+-]/;
+
+// sorts the keys of all objects, to make the JSON comparable
+function canonical(x) {
+    if (x instanceof Array) {
+        return x.map(canonical);
+    }
+    if (x !== null && typeof x === 'object') {
+        return Object.fromEntries(Object.keys(x).sort().map(k => [k, canonical(x[k])]));
+    }
+    return x;
+}
+
+function evalReference(file) {
+    try {
+        const out = execFileSync('nix-instantiate', ['--eval', '--strict', '--json', file], {
+            encoding: 'utf8',
+            stdio: ['ignore', 'pipe', 'pipe'],
+        });
+        return 'o' + JSON.stringify(canonical(JSON.parse(out)));
+    } catch (e) {
+        return 'e' + (e.stderr || e.message).trim();
+    }
+}
+
+async function evalTranslated(prelude, src, name) {
+    let js;
+    try {
+        [js] = translate(src, name);
+    } catch (e) {
+        return 'e' + e;
+    }
+    const ctx = vm.createContext({});
+    vm.runInContext(prelude, ctx);
+    vm.runInContext('nixEvalStart(function(nixRt,nixBlti){' + js + '\n});', ctx);
+    for (let i = 0; ctx.nixEvalOutput === null; i++) {
+        if (i >= 1000) {
+            return 'etimeout';
+        }
+        await new Promise(resolve => setTimeout(resolve, 1));
+    }
+    const out = ctx.nixEvalOutput;
+    return out[0] === 'o' ? 'o' + JSON.stringify(canonical(JSON.parse(out.slice(1)))) : out;
+}
+
+if (process.env.NIX2JS_CONFORMANCE !== '1') {
+    console.log('skipped (set NIX2JS_CONFORMANCE=1 to compare against nix-instantiate)');
+    process.exit(0);
+}
+
+const root = path.dirname(fileURLToPath(import.meta.url));
+const dir = path.join(root, 'docs/testset');
+const prelude = await fs.readFile(path.join(root, 'src/eval_prelude.js'), 'utf8');
+let failed = false;
+for (const i of (await fs.readdir(dir)).filter(x => x.endsWith('.nix')).sort()) {
+    const file = path.join(dir, i);
+    const src = await fs.readFile(file, 'utf8');
+    const header = src.match(/^# expected: (.*)$/m);
+    if (!header || header[1].startsWith('error') || IMPURE.test(src.replace(/^#.*$/mg, ''))) {
+        console.log(i + ': skipped');
+        continue;
+    }
+    const reference = evalReference(file);
+    const translated = await evalTranslated(prelude, src, i);
+    // error messages differ, only the outcome is compared
+    if (reference === translated || (reference[0] === 'e' && translated[0] === 'e')) {
+        console.log(i + ': ok');
+    } else {
+        failed = true;
+        console.log(i + ': MISMATCH\n  nix:    ' + reference + '\n  nix2js: ' + translated);
+    }
+}
+if (failed) {
+    process.exitCode = 1;
+}
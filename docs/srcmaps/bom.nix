@@ -0,0 +1,5 @@
+﻿# starts with a UTF-8 BOM, which is ignored
+let
+  x = 1;
+in
+[ x ]
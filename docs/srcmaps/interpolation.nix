@@ -0,0 +1,2 @@
+x:
+"${x}-${toString 1}"
@@ -0,0 +1,4 @@
+# expected: error: (intermediate value) is not a function
+# `1 2` is a single application (like in Nix), not two root expressions;
+# it fails when evaluated, the message comes from the JS engine
+1 2
@@ -0,0 +1,8 @@
+# expected: error: assertion failed: line 6: builtins.all (x: x > 0) [ 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 ...
+# only the beginning of long conditions is embedded into the output
+let
+  xs = [ 1 2 3 ];
+in
+assert builtins.length xs == 3;
+assert builtins.all (x: x > 0) [ 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 (-1) ];
+xs
@@ -0,0 +1,5 @@
+# expected: [ [ 1 3 2 ] [ "a" "a+" "b" ] ]
+let
+  xs = { b = 2; "a+" = 3; a = 1; };
+in
+[ (builtins.attrValues xs) (builtins.attrNames xs) ]
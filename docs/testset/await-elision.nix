@@ -0,0 +1,12 @@
+# expected: [ 3 "b" 2 false ]
+# lambda bodies and lazy wrappers are returned from async functions,
+# which flush them anyways, so they are emitted without `await`
+let
+  f = x: if x.c then x.a else x.b;
+  g = { a, b ? a }: b;
+in [
+  (f { a = 3; b = 4; c = true; })
+  (g { a = "b"; })
+  ((y: y.z) { z = 2; })
+  (builtins.tryEval (f { c = throw "x"; })).success
+]
@@ -0,0 +1,6 @@
+﻿# expected: [ 1 ]
+# starts with a UTF-8 BOM, which is ignored
+let
+  x = 1;
+in
+[ x ]
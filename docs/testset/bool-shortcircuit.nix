@@ -0,0 +1,6 @@
+# expected: [ true false true ]
+[
+  (true || throw "x")
+  (false && throw "x")
+  (false -> throw "x")
+]
@@ -0,0 +1,2 @@
+# expected: 2
+builtins.elemAt ([ (throw "x") ] ++ [ 2 ]) 1
@@ -0,0 +1,5 @@
+# expected: [ [ 1 2 3 ] 3 ]
+[
+  (builtins.concatLists [ [ 1 2 ] [ 3 ] [ ] ])
+  (builtins.elemAt (builtins.concatLists [ [ (throw "x") ] [ 2 3 ] ]) 2)
+]
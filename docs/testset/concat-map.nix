@@ -0,0 +1,3 @@
+# expected: 1
+# only the list structure gets forced, not the elements
+builtins.elemAt (builtins.concatMap (x: [ x x ]) [ 1 (throw "z") ]) 1
@@ -0,0 +1,2 @@
+# expected: error: concatStringsSep: value is int while a string was expected
+builtins.concatStringsSep 1 [ "a" ]
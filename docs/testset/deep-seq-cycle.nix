@@ -0,0 +1,3 @@
+# expected: 1
+# already forced values aren't visited again, thus this terminates
+let x = { self = x; }; in builtins.deepSeq x 1
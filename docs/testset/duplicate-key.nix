@@ -0,0 +1,5 @@
+# expected: error: line 3: attribute 'a' already defined
+{
+  a = 1;
+  a = 2;
+}
@@ -0,0 +1,2 @@
+# expected: error: elemAt: value is float while a int was expected
+builtins.elemAt [ 1 2 3 ] 1.5
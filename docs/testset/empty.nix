@@ -0,0 +1,3 @@
+# expected: error: line 0: empty Nix expression
+# (a file consisting only of comments and whitespace)
+
@@ -0,0 +1,9 @@
+# with `--entry-export pkgs --export-keys`, every key becomes a separate export:
+#   export const hello=async function(nixRt,nixBlti){...};
+#   export const answer=async function(nixRt,nixBlti){...};
+# without `--export-keys` (or for other roots), only `pkgs` gets exported
+# expected: { answer = 42; hello = "hello world"; }
+{
+  hello = "hello" + " world";
+  answer = 6 * 7;
+}
@@ -0,0 +1,14 @@
+# expected: [ (-0.0) 1.0e308 1.0e-308 true true "0.500000" "1.000000" "-0.000000" "100000000000000001097906" 316 ]
+# `toString` formats floats like C's `%f`, without an exponent
+[
+  (-0.0)
+  1.0e308
+  1.0e-308
+  (-0.0 == 0.0)
+  (1.0e-308 > 0)
+  (builtins.toString 0.5)
+  (builtins.toString 1.0)
+  (builtins.toString (-0.0))
+  (builtins.substring 0 24 (builtins.toString 1.0e308))
+  (builtins.stringLength (builtins.toString 1.0e308))
+]
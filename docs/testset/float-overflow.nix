@@ -0,0 +1,3 @@
+# expected: error: line 2: invalid float '1.0e400'
+# (computed infinities are fine, but `toJSON` rejects them)
+1.0e400
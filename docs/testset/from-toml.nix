@@ -0,0 +1,6 @@
+# expected: [ { a = 1; b = { c = "x"; d = [ true 1.5 ]; }; } { } ]
+# literal strings are parsed at translation time
+[
+  (builtins.fromTOML "a = 1\n[b]\nc = \"x\"\nd = [ true, 1.5 ]\n")
+  (fromTOML "")
+]
@@ -0,0 +1,7 @@
+# expected: [ { } { } { } ]
+# builtins aren't pattern lambdas, thus have no formals
+[
+  (builtins.functionArgs builtins.map)
+  (builtins.functionArgs map)
+  (builtins.functionArgs (builtins.foldl' (a: b: a) 0))
+]
@@ -0,0 +1,7 @@
+# expected: [ true true { } { x = false; y = true; } ]
+[
+  (builtins.isFunction (a: b: a))
+  (builtins.isFunction ((a: b: a) 1))
+  (builtins.functionArgs (a: a))
+  (builtins.functionArgs ({ x, y ? 1 }: x))
+]
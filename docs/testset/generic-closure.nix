@@ -0,0 +1,15 @@
+# expected: [ [ 1 2 3 ] [ "a" "b" ] ]
+[
+  (map (i: i.key) (builtins.genericClosure {
+    startSet = [ { key = 1; } { key = 1; } ];
+    operator = item:
+      if item.key < 3 then [ { key = item.key + 1; } { key = 1; } ] else [ ];
+  }))
+  (map (i: i.key.name) (builtins.genericClosure {
+    startSet = [ { key = { type = "derivation"; name = "a"; }; } ];
+    operator = item: [
+      { key = { type = "derivation"; name = "a"; }; }
+      { key = { type = "derivation"; name = "b"; }; }
+    ];
+  }))
+]
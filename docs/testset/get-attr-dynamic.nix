@@ -0,0 +1,6 @@
+# expected: [ 1 false true ]
+[
+  (builtins.getAttr ("a" + "b") { ab = 1; })
+  (builtins.hasAttr "x" { })
+  (builtins.hasAttr "constructor" { constructor = null; })
+]
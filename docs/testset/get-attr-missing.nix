@@ -0,0 +1,2 @@
+# expected: error: attribute 'ab' missing
+builtins.getAttr ("a" + "b") { a = 1; }
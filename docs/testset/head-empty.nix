@@ -0,0 +1,2 @@
+# expected: error: builtins.head called on empty list
+builtins.head [ ]
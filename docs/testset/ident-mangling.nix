@@ -0,0 +1,9 @@
+# expected: [ 1 2 3 4 5 ]
+let
+  foo = 1;
+  nix__foo = 2;
+  a-b = 3;
+  a'_b = 4;
+  await = 5;
+in
+[ foo nix__foo a-b a'_b await ]
@@ -0,0 +1,7 @@
+# expected: [ true true false ]
+# the rhs of `->` only gets evaluated if the lhs is true
+[
+  (false -> throw "x")
+  (true -> true)
+  ((x: x) true -> false)
+]
@@ -0,0 +1 @@
+{ x }: x + 1
@@ -0,0 +1,4 @@
+# expected: 2
+# the import result has to be awaited before it can be applied:
+#   (await (nixBltiRT.import)(...))(...)
+import ./import-apply-f.nix { x = 1; }
@@ -0,0 +1,11 @@
+# expected: "foo\n  a\n  b bar\n\n  c d\nbaz\n"
+# the common indentation (4) is stripped from the literal lines only:
+# the interpolated value keeps its own whitespace, and the literal after
+# `${x}` continues the line, thus its leading space is kept
+let x = "a\n  b"; in ''
+    foo
+      ${x} bar
+
+      c${" "}d
+    baz
+''
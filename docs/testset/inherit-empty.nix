@@ -0,0 +1,9 @@
+# expected: 1
+# the source of an empty `inherit (...)` is never forced
+let
+  x = {
+    inherit;
+    inherit (throw "unused");
+    a = 1;
+  };
+in x.a
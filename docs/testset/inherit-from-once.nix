@@ -0,0 +1,6 @@
+# expected: { a = 1; b = 2; }
+# `src` gets forced (and traced) exactly once
+let
+  src = builtins.trace "src" { a = 1; b = 2; };
+in
+{ inherit (src) a b; }
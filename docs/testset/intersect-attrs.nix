@@ -0,0 +1,7 @@
+# expected: [ { a = 3; c = 5; } [ "a" "c" ] ]
+let
+  # the values come from the second set, `c` isn't forced by `attrNames`
+  xs = builtins.intersectAttrs { a = 1; c = 2; d = 0; } { a = 3; b = 4; c = 5; };
+  ys = builtins.intersectAttrs { a = null; c = null; } { a = 1; c = throw "c"; };
+in
+[ xs (builtins.attrNames ys) ]
@@ -0,0 +1,12 @@
+# expected: true
+let
+  x = {
+    a = [ 1 2 ];
+    b = "x";
+    c = 1.5;
+    d = true;
+    e = null;
+    f = { g = { h = [ false "y" ]; }; };
+  };
+in
+builtins.fromJSON (builtins.toJSON x) == x
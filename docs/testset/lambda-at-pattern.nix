@@ -0,0 +1,5 @@
+# expected: [ 1 1 ]
+[
+  (({ a }@args: args.a) { a = 1; })
+  ((args@{ a }: args.a) { a = 1; })
+]
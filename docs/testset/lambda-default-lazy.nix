@@ -0,0 +1,6 @@
+# expected: [ 1 2 ]
+# additionally, `({ a ? throw "x" }: a) { }` must throw "x"
+[
+  (({ a ? throw "x" }: 1) { })
+  (({ a ? throw "x" }: a) { a = 2; })
+]
@@ -0,0 +1,8 @@
+# expected: [ 1 2 ]
+let
+  xs = builtins.genList (i: if i == 0 then 1 else throw "x") 10;
+in
+[
+  (builtins.head xs)
+  (builtins.length (builtins.tail (builtins.tail (builtins.tail xs))) - 5)
+]
@@ -0,0 +1,6 @@
+# expected: [ 2 3 4 ]
+[
+  (builtins.length [ (throw "a") (throw "b") ])
+  (builtins.length (builtins.genList (i: throw "c") 3))
+  (builtins.length (builtins.concatMap (x: [ x x ]) [ (throw "d") (throw "e") ]))
+]
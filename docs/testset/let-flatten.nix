@@ -0,0 +1,18 @@
+# expected: [ 3 2 [ 1 5 ] ]
+# sequential `let`s get flattened into a single block, unless they shadow
+let
+  a = 1;
+  s = { x = 5; };
+in
+let
+  b = a + 1;
+  inherit (s) x;
+in
+let
+  c = a + b;
+in
+[
+  c
+  (let a = 2; in a)
+  (let y = a; in let a = y; in [ a x ])
+]
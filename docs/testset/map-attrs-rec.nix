@@ -0,0 +1,7 @@
+# expected: [ { a = 1; b = 2; } { a = 10; b = 20; c = 30; } ]
+# the recursive references are resolved via the scope of the `rec` set,
+# independently of the iteration in `mapAttrs`
+[
+  (builtins.mapAttrs (n: v: v) (rec { a = 1; b = a + 1; }))
+  (builtins.mapAttrs (n: v: v * 10) (rec { a = 1; b = c - a; c = 3; }))
+]
@@ -0,0 +1,7 @@
+# expected: [ [ "a" "b" "c" "d" ] 10 5 6 ]
+let
+  # `b` gets overridden, `c` is never forced
+  xs = builtins.mapAttrs (n: v: if n == "a" then v * 10 else throw n) { a = 1; b = 2; c = 3; };
+  ys = xs // { b = 5; d = 6; };
+in
+[ (builtins.attrNames ys) ys.a ys.b ys.d ]
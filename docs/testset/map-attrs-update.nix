@@ -0,0 +1,5 @@
+# expected: [ [ "a" "b" ] 3 ]
+let
+  xs = builtins.mapAttrs (n: v: v + 1) ({ a = 1; } // { b = 2; });
+in
+[ (builtins.attrNames xs) xs.b ]
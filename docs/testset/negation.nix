@@ -0,0 +1,5 @@
+# expected: [ 5 3 (-1.5) 3 ]
+let
+  a = 1.5;
+in
+[ (- -5) (1 - -2) (-a) (1--2) ]
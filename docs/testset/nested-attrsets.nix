@@ -0,0 +1,11 @@
+# expected: { a = { b = { c = { d = 1; }; e = { f = { g = 2; }; }; }; }; h = { i = 1; }; }
+let
+  x = 1;
+in
+{
+  a.b = {
+    c.d = x;
+    e = { f.g = 2; };
+  };
+  h.i = x;
+}
@@ -0,0 +1,4 @@
+# with `nix_version = Some("2.3.16")`, `builtins.nixVersion` is folded into the literal "2.3.16":
+#   nixBltiRT.compareVersions("2.3.16")("2.3")
+# expected: 1
+builtins.compareVersions builtins.nixVersion "2.3"
@@ -0,0 +1,5 @@
+# expected: error: line 3, column 6: unexpected TOKEN_SEMICOLON ...
+# (also reported by `nix2js --parse-only`)
+let
+  a = ;
+in a
@@ -0,0 +1,4 @@
+# expected: error: line 3, column 23: unexpected TOKEN_PAREN_CLOSE ...
+# with `--recover`, the syntax error becomes a warning and the incomplete
+# operand becomes `nixRt.parseError(...)`, which is never forced: 1
+builtins.head [ 1 (2 + ) ]
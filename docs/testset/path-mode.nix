@@ -0,0 +1,5 @@
+# emitted depending on `--path-mode`:
+#   store (default):   nixRt.export("Relative","./foo")
+#   string:            "./foo"
+#   custom:exportPath: nixRt.exportPath("Relative","./foo")
+./foo
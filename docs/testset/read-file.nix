@@ -0,0 +1,2 @@
+# expected: "hello\n"
+builtins.readFile ./read-file.txt
@@ -0,0 +1,6 @@
+# expected: error: unknown identifier a
+# dynamic keys aren't part of the recursive scope
+rec {
+  ${"a"} = 1;
+  b = a;
+}
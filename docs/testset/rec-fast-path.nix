@@ -0,0 +1,7 @@
+# expected: { plain = { a = 1; b = 2; }; recursive = { a = 1; b = 2; }; }
+# `plain` doesn't reference its own keys, and is emitted as plain object
+# (like a non-recursive attrset), `recursive` still needs the `mkScope` IIFE
+{
+  plain = rec { a = 1; b = 2; };
+  recursive = rec { a = 1; b = a + 1; };
+}
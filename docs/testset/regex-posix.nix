@@ -0,0 +1,8 @@
+# expected: [ [ "foo" ] null [ "12" "A" ] [ null ] [ "" [ ] "a" [ ] "b" ] ]
+[
+  (builtins.match "(.*)\\.nix" "foo.nix")
+  (builtins.match "[[:alpha:]]+" "ab1")
+  (builtins.match "([[:digit:]]+)-([[:upper:]])" "12-A")
+  (builtins.match "a(b)?" "a")
+  (builtins.split "[[:space:]]+" " a  b")
+]
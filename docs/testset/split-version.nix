@@ -0,0 +1,10 @@
+# expected: [ [ ] [ "1" "2" "pre_" "3" ] 0 0 (-1) (-1) 1 ]
+[
+  (builtins.splitVersion "")
+  (builtins.splitVersion "1..2pre_3")
+  (builtins.compareVersions "" "")
+  (builtins.compareVersions "1..2" "1.2")
+  (builtins.compareVersions "" "1")
+  (builtins.compareVersions "1" "1a")
+  (builtins.compareVersions "2.3.1" "2.3a")
+]
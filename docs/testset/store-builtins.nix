@@ -0,0 +1,7 @@
+# expected (with the default store dir):
+# [ "/nix/store" "/nix/store/abc-foo" "/1rz4g4znpzjwh1xymhjpm42vipw92pr73vdgl6xs1hycac8kf2n9" ]
+[
+  builtins.storeDir
+  (builtins.storePath "/nix/store/abc-foo")
+  (builtins.placeholder "out")
+]
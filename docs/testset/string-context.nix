@@ -0,0 +1,14 @@
+# expected: [ true true false false true ]
+# `out` stands in for the `outPath` of a derivation
+let
+  drv = "/nix/store/zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz-foo.drv";
+  out = builtins.appendContext "/nix/store/zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz-foo" {
+    ${drv} = { outputs = [ "out" ]; };
+  };
+in [
+  (builtins.hasContext out)
+  (builtins.hasContext (out + "/bin"))
+  (builtins.hasContext (builtins.unsafeDiscardStringContext out))
+  (builtins.hasContext "/nix/store/zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz-foo")
+  (out == "/nix/store/zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz-foo")
+]
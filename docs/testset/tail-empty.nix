@@ -0,0 +1,2 @@
+# expected: error: builtins.tail called on empty list
+builtins.tail [ ]
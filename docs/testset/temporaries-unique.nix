@@ -0,0 +1,15 @@
+# expected: { a = { x = 1; }; b = { c = { d = 2; }; x = 1; z = { e = 3; y = 2; }; }; y = 2; }
+# every generated temporary (`nixInhR<n>`, `nixAttrsScope<n>`) gets its own name,
+# nested scopes never shadow each other
+let
+  s = { x = 1; y = 2; };
+in
+{
+  a.x = 1;
+  inherit (s) y;
+  b = {
+    c.d = 2;
+    inherit (s) x;
+    z = { inherit (s) y; ${"e"} = 3; };
+  };
+}
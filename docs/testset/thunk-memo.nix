@@ -0,0 +1,3 @@
+# expected: 2
+# the shared thunk is evaluated only once, thus "trace: once" gets printed once
+let x = builtins.trace "once" 1; in x + x
@@ -0,0 +1,5 @@
+# expected: [ "{\"a\":2,\"b\":1}" "{\"a\":{\"c\":[2],\"d\":null},\"b\":1}" ]
+[
+  (builtins.toJSON { b = 1; a = 2; })
+  (builtins.toJSON { b = 1; a = { d = null; c = [ (1 + 1) ]; }; })
+]
@@ -0,0 +1,2 @@
+# expected: [ "1" "" "1 2" "" "42" ]
+map builtins.toString [ true false [ 1 2 ] null 42 ]
@@ -0,0 +1,4 @@
+# with `--trace-mode suppress`, the message is dropped instead of being passed to `nixRt.trace`:
+#   (_=>x=>x)("message")(42)
+# expected: 42
+builtins.trace "message" 42
@@ -0,0 +1,2 @@
+# expected: error: evaluation aborted with the following error message: 'x'
+builtins.tryEval (builtins.abort "x")
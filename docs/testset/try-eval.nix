@@ -0,0 +1,7 @@
+# expected: [ false false true ]
+# `abort` isn't catchable, see try-eval-abort.nix
+map (x: (builtins.tryEval x).success) [
+  (throw "x")
+  (assert false; 1)
+  (assert true; 1)
+]
@@ -0,0 +1,14 @@
+# expected: [ "int" "float" "bool" "string" "path" "null" "set" "list" "lambda" "int" ]
+# NOTE: "path" requires `nixRt.export` to return a `nixBlti.NixPath`
+map builtins.typeOf [
+  1
+  1.5
+  true
+  "a"
+  ./foo
+  null
+  { }
+  [ ]
+  (a: a)
+  (builtins.head [ 2 ])
+]
@@ -0,0 +1,17 @@
+# expected: [ true true true true true true false false false true false ]
+let
+  lazy = x: builtins.head [ x ];
+in
+[
+  (builtins.isInt 1)
+  (builtins.isFloat 1.5)
+  (builtins.isString (lazy "a"))
+  (builtins.isBool (lazy true))
+  (builtins.isList (lazy [ ]))
+  (builtins.isAttrs (lazy { }))
+  (builtins.isAttrs null)
+  (builtins.isAttrs [ ])
+  (builtins.isList { })
+  (builtins.isFloat 1.0)
+  (builtins.isInt 1.0)
+]
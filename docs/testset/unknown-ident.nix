@@ -0,0 +1,6 @@
+# expected: error: line 3: unknown identifier foo (in `y = foo + x;`)
+let
+  x = 1;
+  y = foo + x;
+in
+y
@@ -0,0 +1,12 @@
+# the default of `or` is only forced if the key is missing from both operands of `//`
+# expected: [ "override" "base" "default" "override" ]
+let
+  base = { foo = "base"; };
+  override = { foo = "override"; };
+in
+[
+  (({ } // override).foo or (throw "unreachable"))
+  ((base // { }).foo or (throw "unreachable"))
+  (({ bar = 1; } // { baz = 2; }).foo or "default")
+  ((base // override).foo or (throw "unreachable"))
+]
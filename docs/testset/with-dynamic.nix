@@ -0,0 +1,8 @@
+# expected: 3
+# warning (e.g. via `nix2js lint`, lines are 0-based):
+#   line 6: namespace of 'with' isn't statically known, identifiers inside of it can't be checked
+let
+  mk = x: { a = x; };
+in
+with mk 1;
+a + 2
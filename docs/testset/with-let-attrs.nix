@@ -0,0 +1,8 @@
+# expected: [ 1 2 3 5 ]
+# all identifiers are resolved statically to `lib`/`lib2`,
+# without going through the dynamic scope
+let
+  lib = { a = 1; b = 2; };
+  lib2 = { c-d = 3; x = 4; };
+in
+with lib2; with lib; [ a b c-d (x + 1) ]
@@ -0,0 +1,5 @@
+# expected: { names = [ "b" ]; value = { b = 1; }; }
+# the scope objects of `with` and `rec` are never observable as attrsets,
+# only the own keys of `rec` get extracted
+let value = with { a = 1; }; rec { b = a; };
+in { names = builtins.attrNames value; inherit value; }
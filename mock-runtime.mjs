@@ -130,6 +130,10 @@ function buildRT(opath) {
             }
         },
         'import': import_,
+        'parseError': msg => {
+            throw new nixBlti.NixEvalError(opath + ': ' + msg);
+        },
+        'readFile': async xpath => await fs.readFile(await xpath, 'utf8'),
         'pathExists': async xpath => {
             try {
                 await fs.access(await xpath, fsconsts.R_OK);
@@ -4,13 +4,18 @@ import {
   initRtDep,
   mkScope,
   mkScopeWith,
+  NixAbortError,
   NixEvalError,
+  NixStringWithContext,
+  NixPath,
   nixOp,
   PLazy,
   ScopeError,
+  setFunctionArgs,
 } from "./index.js";
 import { isEqual } from "lodash-es";
 import assert from "webassert";
+import { createHash } from "crypto";
 
 function mkMut(i) {
   return { i: i };
@@ -109,13 +114,29 @@ describe("mkScopeWith", function () {
     assert_eq(sc2[allKeys], ["x"], "(keys2)");
     assert_eq(sc2["x"], 1, "(get)");
   });
+
+  it("should not leak into extracted attrsets", async function () {
+    // `with { a = 1; }; rec { b = a; }`
+    let sc = mkScope(mkScopeWith(mkScopeWith(), { a: 1 }));
+    sc["b"] = sc["a"];
+    let ret = sc[extractScope];
+    assert_eq(Object.keys(ret), ["b"], "(keys)");
+    assert_eq(await xblti.attrNames(ret), ["b"], "(attrNames)");
+    assert_eq(Object.getPrototypeOf(ret), null, "(proto)");
+  });
+
+  it("should not be enumerable as attrset", async function () {
+    let sc = mkScopeWith(mkScopeWith(), { a: 1 });
+    assert_eq(await xblti.attrNames(sc), [], "(attrNames)");
+  });
 });
 
 describe("add", function () {
   it("should work if arguments are correct", async function () {
-    assert_eq(await xblti.add(1200)(567), 1767, "integer");
-    assert_eq(await xblti.add(-100)(567), 467, "integer (2)");
-    assert_eq(await xblti.add(203)(-500), -297, "integer (3)");
+    assert_eq(await xblti.add(1200n)(567n), 1767n, "integer");
+    assert_eq(await xblti.add(-100n)(567n), 467n, "integer (2)");
+    assert_eq(await xblti.add(203n)(-500n), -297n, "integer (3)");
+    assert_eq(await xblti.add(1n)(0.5), 1.5, "int/float");
   });
   describe("should report errors correctly", function () {
     it("string/string", async function () {
@@ -133,26 +154,26 @@ describe("add", function () {
     });
     it("int/string", async function () {
       try {
-        console.log(await xblti.add(0)("oops"));
+        console.log(await xblti.add(0n)("oops"));
         assert(false, "unreachable");
       } catch (e) {
         assert(e instanceof TypeError, "error kind");
         assert_eq(
           e.message,
-          "builtins.add: given types mismatch (number != string)",
+          "builtins.add: given types mismatch (bigint != string)",
           "message"
         );
       }
     });
     it("string/int", async function () {
       try {
-        console.log(await xblti.add("oops")(0));
+        console.log(await xblti.add("oops")(0n));
         assert(false, "unreachable");
       } catch (e) {
         assert(e instanceof TypeError, "error kind");
         assert_eq(
           e.message,
-          "builtins.add: given types mismatch (string != number)",
+          "builtins.add: given types mismatch (string != bigint)",
           "message"
         );
       }
@@ -162,25 +183,79 @@ describe("add", function () {
 
 describe("compareVersions", function () {
   it("should work for simple cases", async function () {
-    assert_eq(await xblti.compareVersions("1.0")("2.3"), -1, "(1)");
-    assert_eq(await xblti.compareVersions("2.3")("1.0"), 1, "(2)");
-    assert_eq(await xblti.compareVersions("2.1")("2.3"), -1, "(3)");
-    assert_eq(await xblti.compareVersions("2.3")("2.3"), 0, "(4)");
-    assert_eq(await xblti.compareVersions("2.5")("2.3"), 1, "(5)");
-    assert_eq(await xblti.compareVersions("3.1")("2.3"), 1, "(6)");
+    assert_eq(await xblti.compareVersions("1.0")("2.3"), -1n, "(1)");
+    assert_eq(await xblti.compareVersions("2.3")("1.0"), 1n, "(2)");
+    assert_eq(await xblti.compareVersions("2.1")("2.3"), -1n, "(3)");
+    assert_eq(await xblti.compareVersions("2.3")("2.3"), 0n, "(4)");
+    assert_eq(await xblti.compareVersions("2.5")("2.3"), 1n, "(5)");
+    assert_eq(await xblti.compareVersions("3.1")("2.3"), 1n, "(6)");
   });
   it("should work for complex cases", async function () {
-    assert_eq(await xblti.compareVersions("2.3.1")("2.3"), 1, "(7)");
-    assert_eq(await xblti.compareVersions("2.3.1")("2.3a"), 1, "(8)");
-    assert_eq(await xblti.compareVersions("2.3pre1")("2.3"), -1, "(9)");
-    assert_eq(await xblti.compareVersions("2.3")("2.3pre1"), 1, "(10)");
-    assert_eq(await xblti.compareVersions("2.3pre3")("2.3pre12"), -1, "(11)");
-    assert_eq(await xblti.compareVersions("2.3pre12")("2.3pre3"), 1, "(12)");
-    assert_eq(await xblti.compareVersions("2.3a")("2.3c"), -1, "(13)");
-    assert_eq(await xblti.compareVersions("2.3c")("2.3a"), 1, "(14)");
-    assert_eq(await xblti.compareVersions("2.3pre1")("2.3c"), -1, "(15)");
-    assert_eq(await xblti.compareVersions("2.3pre1")("2.3q"), -1, "(16)");
-    assert_eq(await xblti.compareVersions("2.3q")("2.3pre1"), 1, "(17)");
+    assert_eq(await xblti.compareVersions("2.3.1")("2.3"), 1n, "(7)");
+    assert_eq(await xblti.compareVersions("2.3.1")("2.3a"), 1n, "(8)");
+    assert_eq(await xblti.compareVersions("2.3pre1")("2.3"), -1n, "(9)");
+    assert_eq(await xblti.compareVersions("2.3")("2.3pre1"), 1n, "(10)");
+    assert_eq(await xblti.compareVersions("2.3pre3")("2.3pre12"), -1n, "(11)");
+    assert_eq(await xblti.compareVersions("2.3pre12")("2.3pre3"), 1n, "(12)");
+    assert_eq(await xblti.compareVersions("2.3a")("2.3c"), -1n, "(13)");
+    assert_eq(await xblti.compareVersions("2.3c")("2.3a"), 1n, "(14)");
+    assert_eq(await xblti.compareVersions("2.3pre1")("2.3c"), -1n, "(15)");
+    assert_eq(await xblti.compareVersions("2.3pre1")("2.3q"), -1n, "(16)");
+    assert_eq(await xblti.compareVersions("2.3q")("2.3pre1"), 1n, "(17)");
+  });
+  it("should handle empty and weird inputs", async function () {
+    assert_eq(await xblti.compareVersions("")(""), 0n, "(18)");
+    assert_eq(await xblti.compareVersions("")("1"), -1n, "(19)");
+    assert_eq(await xblti.compareVersions("1..2")("1.2"), 0n, "(20)");
+    assert_eq(await xblti.compareVersions("1")("1a"), -1n, "(21)");
+    assert_eq(await xblti.compareVersions("1.01")("1.1"), 0n, "(22)");
+  });
+});
+
+describe("splitVersion", function () {
+  it("should handle empty and weird inputs", async function () {
+    assert_eq(await xblti.splitVersion(""), [], "(1)");
+    assert_eq(await xblti.splitVersion("1.2.3"), ["1", "2", "3"], "(2)");
+    assert_eq(await xblti.splitVersion("1..2--3"), ["1", "2", "3"], "(3)");
+    assert_eq(
+      await xblti.splitVersion("2.3pre1"),
+      ["2", "3", "pre", "1"],
+      "(4)"
+    );
+    assert_eq(await xblti.splitVersion("1_a+b"), ["1", "_a+b"], "(5)");
+  });
+});
+
+describe("match", function () {
+  it("should translate POSIX character classes", async function () {
+    assert_eq(await xblti.match("[[:alpha:]]+")("abc"), [], "(1)");
+    assert_eq(await xblti.match("[[:alpha:]]+")("ab1"), null, "(2)");
+    assert_eq(
+      await xblti.match("([[:digit:]]+)-([[:upper:]])")("12-A"),
+      ["12", "A"],
+      "(3)"
+    );
+    assert_eq(await xblti.match("[]a]+")("]a]"), [], "(4)");
+    assert_eq(await xblti.match("[\\]+")("\\\\"), [], "(5)");
+  });
+  it("should return null for unmatched groups", async function () {
+    assert_eq(await xblti.match("(.*)\\.nix")("foo.nix"), ["foo"], "(1)");
+    assert_eq(await xblti.match("a(b)?")("a"), [null], "(2)");
+  });
+});
+
+describe("split", function () {
+  it("should interleave the captures", async function () {
+    assert_eq(
+      await xblti.split("(a)|b")("xaybz"),
+      ["x", ["a"], "y", [null], "z"],
+      "(1)"
+    );
+    assert_eq(
+      await xblti.split("[[:space:]]+")(" a  b "),
+      ["", [], "a", [], "b", [], ""],
+      "(2)"
+    );
   });
 });
 
@@ -201,6 +276,21 @@ describe("tryEval", function () {
     })();
     assert_eq(await xblti.tryEval(x), { success: false, value: false });
   });
+  it("should catch failed assertions", async function () {
+    assert_eq(
+      await xblti.tryEval(xblti.assert("line 1: false")(false)),
+      { success: false, value: false }
+    );
+  });
+  it("should not catch abort", async function () {
+    let caught = null;
+    try {
+      await xblti.tryEval(xblti.abort("x"));
+    } catch (e) {
+      caught = e;
+    }
+    assert(caught instanceof NixAbortError);
+  });
   it("should work for impure.nix/try<nixpkgs-overlays>", async function() {
     assert_eq(await PLazy.from(async () => {
       let nix__try = async (nix__x) => async (nix__def) =>
@@ -225,11 +315,32 @@ describe("tryEval", function () {
   });
 });
 
+describe("inherit from", function () {
+  // generated from `let src = ...; inherit (src) a b; in [ a b ]`
+  it("should force the source once, and only when needed", async function () {
+    let cnt = 0;
+    let res = await (async (nixInScope) => {
+      nixInScope.src = PLazy.from(async () => {
+        cnt += 1;
+        return Object.assign(Object.create(null), { a: 1, b: 2 });
+      });
+      const nixInhR0 = PLazy.from(async () => await nixInScope.src);
+      nixInScope.a = PLazy.from(async () => (await nixInhR0).a);
+      nixInScope.b = PLazy.from(async () => (await nixInhR0).b);
+      assert_eq(cnt, 0, "lazy");
+      return [await nixInScope.a, await nixInScope.b];
+    })(mkScope(mkScopeWith()));
+    assert_eq(res, [1, 2], "result");
+    assert_eq(cnt, 1, "forced once");
+  });
+});
+
 describe("+", function () {
   it("should work if arguments are correct", async function () {
-    assert_eq(await nixOp.Add(1200, 567), 1767, "integer");
-    assert_eq(await nixOp.Add(-100, 567), 467, "integer (2)");
-    assert_eq(await nixOp.Add(203, -500), -297, "integer (3)");
+    assert_eq(await nixOp.Add(1200n, 567n), 1767n, "integer");
+    assert_eq(await nixOp.Add(-100n, 567n), 467n, "integer (2)");
+    assert_eq(await nixOp.Add(203n, -500n), -297n, "integer (3)");
+    assert_eq(await nixOp.Add(0.5, 1n), 1.5, "float/int");
     assert_eq(await nixOp.Add("ab", "cde"), "abcde", "string");
   });
   describe("should report errors correctly", function () {
@@ -263,28 +374,32 @@ describe("+", function () {
 });
 
 it("-", async function () {
-  assert_eq(await nixOp.Sub(1200, 567), 633, "integer");
-  assert_eq(await nixOp.Sub(-100, 567), -667, "integer (2)");
-  assert_eq(await nixOp.Sub(203, -500), 703, "integer (3)");
+  assert_eq(await nixOp.Sub(1200n, 567n), 633n, "integer");
+  assert_eq(await nixOp.Sub(-100n, 567n), -667n, "integer (2)");
+  assert_eq(await nixOp.Sub(203n, -500n), 703n, "integer (3)");
+  assert_eq(await nixOp.Sub(2n, 0.5), 1.5, "int/float");
 });
 
 it("*", async function () {
-  assert_eq(await nixOp.Mul(50, 46), 2300, "integer");
-  assert_eq(await nixOp.Mul(50004, 1023), 51154092, "integer (2)");
-  assert_eq(await nixOp.Mul(203, -500), -101500, "integer (3)");
-  assert_eq(await nixOp.Mul(-203, 500), -101500, "integer (4)");
-  assert_eq(await nixOp.Mul(-203, -500), 101500, "integer (5)");
+  assert_eq(await nixOp.Mul(50n, 46n), 2300n, "integer");
+  assert_eq(await nixOp.Mul(50004n, 1023n), 51154092n, "integer (2)");
+  assert_eq(await nixOp.Mul(203n, -500n), -101500n, "integer (3)");
+  assert_eq(await nixOp.Mul(-203n, 500n), -101500n, "integer (4)");
+  assert_eq(await nixOp.Mul(-203n, -500n), 101500n, "integer (5)");
+  assert_eq(await nixOp.Mul(2n, 1.0), 2.0, "int/float");
 });
 
 describe("/", function () {
   it("should work if arguments are correct", async function () {
-    assert_eq(await nixOp.Div(1, 1), 1, "integer");
-    assert_eq(await nixOp.Div(8, 4), 2, "integer (2)");
-    assert_eq(await nixOp.Div(754677, 1331), 567, "integer (3)");
+    assert_eq(await nixOp.Div(1n, 1n), 1n, "integer");
+    assert_eq(await nixOp.Div(8n, 4n), 2n, "integer (2)");
+    assert_eq(await nixOp.Div(754677n, 1331n), 567n, "integer (3)");
+    assert_eq(await nixOp.Div(-7n, 2n), -3n, "integer (truncated)");
+    assert_eq(await nixOp.Div(7n, 2.0), 3.5, "int/float");
   });
   it("should catch division-by-zero", async function () {
     try {
-      console.log(await nixOp.Div(1, 0));
+      console.log(await nixOp.Div(1n, 0n));
       assert(false, "unreachable");
     } catch (e) {
       assert(e instanceof RangeError, "error kind");
@@ -303,12 +418,508 @@ describe("//", function () {
     assert_eq(await nixOp.Update(a, b), { a: { i: 2 } }, "//");
     assert_eq(a, { a: { i: 0 } }, "original objects shouldn't be modified");
   });
+  it("should be usable with mapAttrs", async function () {
+    let tmp = await xblti.mapAttrs(async (n) => async (v) => (await v) + 1)(
+      nixOp.Update({ a: 1 }, { b: 2 })
+    );
+    assert_eq(await xblti.attrNames(tmp), ["a", "b"], "attrNames");
+    assert_eq(await tmp.b, 3, "value");
+  });
+  it("should merge mapAttrs results as the left operand", async function () {
+    let tmp = await nixOp.Update(
+      xblti.mapAttrs(async (n) => async (v) => {
+        if (n !== "a") throw new NixEvalError(n);
+        return (await v) * 10;
+      })({ a: 1, b: 2, c: 3 }),
+      { b: 5, d: 6 }
+    );
+    assert_eq(await xblti.attrNames(tmp), ["a", "b", "c", "d"], "attrNames");
+    assert_eq(await tmp.a, 10, "lazy value from the left");
+    assert_eq(await tmp.b, 5, "overridden value");
+  });
+});
+
+describe("++", function () {
+  it("should concatenate lists", async function () {
+    assert_eq(await nixOp.Concat([1], [2, 3]), [1, 2, 3]);
+  });
+  it("shouldn't force the elements", async function () {
+    let tmp = await nixOp.Concat(
+      [
+        PLazy.from(async () => {
+          throw new NixEvalError("x");
+        }),
+      ],
+      [2]
+    );
+    assert_eq(tmp.length, 2, "length");
+    assert_eq(await xblti.elemAt(tmp)(1n), 2, "elemAt");
+  });
+});
+
+describe("lambda pattern arguments", function () {
+  let throwing = () =>
+    PLazy.from(async () => {
+      throw new NixEvalError("x");
+    });
+  it("shouldn't force unused defaults", async function () {
+    assert_eq(await nixOp._lambdaA2chk({ a: 1 }, "a", throwing()), 1);
+    let tmp = nixOp._lambdaA2chk({}, "a", throwing());
+    try {
+      await tmp;
+      assert(false, "unreachable");
+    } catch (e) {
+      assert(e instanceof NixEvalError, "error kind");
+      assert_eq(e.message, "x", "message");
+    }
+  });
+  it("should reject missing arguments", function () {
+    try {
+      nixOp._lambdaA2chk({}, "a");
+      assert(false, "unreachable");
+    } catch (e) {
+      assert(e instanceof NixEvalError, "error kind");
+    }
+  });
+});
+
+it("toString", async function () {
+  assert_eq(await xblti.toString(true), "1", "true");
+  assert_eq(await xblti.toString(false), "", "false");
+  assert_eq(await xblti.toString(null), "", "null");
+  assert_eq(await xblti.toString(42n), "42", "int");
+  assert_eq(await xblti.toString(0.5), "0.500000", "float");
+  assert_eq(await xblti.toString(1.0), "1.000000", "integral float");
+  assert_eq(await xblti.toString(-0.0), "-0.000000", "negative zero");
+  assert_eq(await xblti.toString(-1e-7), "-0.000000", "rounded to zero");
+  assert_eq(
+    await xblti.toString(1e21),
+    "1000000000000000000000.000000",
+    "no exponent"
+  );
+  assert_eq(await xblti.toString(1e308 * 10), "inf", "infinity");
+  assert_eq(await xblti.toString([1n, 2n]), "1 2", "list");
+  assert_eq(
+    await xblti.toString([true, PLazy.from(async () => [null, "a"])]),
+    "1  a",
+    "nested list"
+  );
+});
+
+it("thunks are memoized", async function () {
+  // `let x = builtins.trace "once" 1; in x + x`
+  let traced = [];
+  let orig = console.debug;
+  console.debug = (i) => traced.push(i);
+  try {
+    let x = PLazy.from(async () => (await xblti.trace("once"))(1n));
+    assert_eq(await nixOp.Add(x, x), 2n, "result");
+  } finally {
+    console.debug = orig;
+  }
+  assert_eq(traced, ["once"], "trace count");
+});
+
+it("deepSeq terminates on self-referential values", async function () {
+  // `let x = { self = x; }; in builtins.deepSeq x 1`
+  let x = Object.create(null);
+  x.self = PLazy.from(async () => x);
+  assert_eq(await (await xblti.deepSeq(x))(1), 1, "result");
+});
+
+describe("lazy lists", function () {
+  let gen = async (i) => {
+    if ((await i) !== 0n) {
+      throw new NixEvalError("x");
+    }
+    return 1;
+  };
+  it("genList + head", async function () {
+    let tmp = await xblti.genList(gen)(10n);
+    assert_eq(tmp.length, 10, "length");
+    assert_eq(await xblti.head(tmp), 1, "head");
+  });
+  it("tail", async function () {
+    let tmp = await xblti.tail(await xblti.genList(gen)(3n));
+    assert_eq(tmp.length, 2, "length");
+  });
+  it("map", async function () {
+    let tmp = await xblti.map(gen)([0n, 1n]);
+    assert_eq(await xblti.elemAt(tmp)(0n), 1, "elemAt");
+  });
+  it("length", async function () {
+    assert_eq(await xblti.length(xblti.genList(gen)(3n)), 3n, "genList");
+    let tmp = xblti.concatMap(async (x) => [x, x])([PLazy.from(() => gen(1n))]);
+    assert_eq(await xblti.length(tmp), 2n, "concatMap");
+  });
+});
+
+describe("concatLists", function () {
+  it("should flatten one level", async function () {
+    assert_eq(await xblti.concatLists([[1, 2], [3], []]), [1, 2, 3]);
+    assert_eq(await xblti.concatLists([[[1]], [2]]), [[1], 2], "nested");
+  });
+  it("shouldn't force the inner elements", async function () {
+    let tmp = await xblti.concatLists([
+      [
+        PLazy.from(async () => {
+          throw new NixEvalError("x");
+        }),
+      ],
+      PLazy.from(async () => [2]),
+    ]);
+    assert_eq(tmp.length, 2, "length");
+    assert_eq(await xblti.elemAt(tmp)(1n), 2, "elemAt");
+  });
 });
 
 it("==", async function () {
-  assert_eq(await nixOp.Equal(1, 1), true);
+  assert_eq(await nixOp.Equal(1n, 1n), true);
+  assert_eq(await nixOp.Equal(1n, 1.0), true, "int/float");
+  assert_eq(await nixOp.Equal([1.5], [1n]), false, "nested int/float");
+  assert_eq(await nixOp.Equal(1n, "1"), false, "int/string");
 });
 
 it("!=", async function () {
-  assert_eq(await nixOp.NotEqual(1, 1), false);
+  assert_eq(await nixOp.NotEqual(1n, 1n), false);
+});
+
+it("unary -", async function () {
+  assert_eq(await nixOp.u_Negate(nixOp.u_Negate(5n)), 5n, "double negation");
+  assert_eq(await nixOp.Sub(1n, nixOp.u_Negate(2n)), 3n, "binary with unary");
+  assert_eq(await nixOp.u_Negate(1.5), -1.5, "float");
+});
+
+describe("&&/||/->", function () {
+  let boom = () =>
+    PLazy.from(async () => {
+      throw new NixEvalError("x");
+    });
+  it("should short-circuit", async function () {
+    assert_eq(await nixOp.Or(true, boom()), true, "||");
+    assert_eq(await nixOp.And(false, boom()), false, "&&");
+    assert_eq(await nixOp.Implication(false, boom()), true, "->");
+  });
+  it("should evaluate the rhs if necessary", async function () {
+    assert_eq(await nixOp.Or(false, true), true, "||");
+    assert_eq(await nixOp.And(true, false), false, "&&");
+    assert_eq(await nixOp.Implication(true, false), false, "->");
+    assert_eq(await nixOp.Implication(true, true), true, "-> (2)");
+  });
+  it("should reject non-booleans", async function () {
+    try {
+      console.log(await nixOp.And(true, 1));
+      assert(false, "unreachable");
+    } catch (e) {
+      assert(e instanceof TypeError, "error kind");
+      assert_eq(
+        e.message,
+        "operator &&: invalid input type (number), expected (boolean)",
+        "message"
+      );
+    }
+  });
+});
+
+describe("store builtins", function () {
+  let sblti = initRtDep({
+    hashString: (ty) => async (s) =>
+      createHash(ty).update(await s).digest("hex"),
+  });
+  it("storeDir", function () {
+    assert_eq(sblti.storeDir, "/nix/store");
+    assert_eq(initRtDep({ storeDir: "/gnu/store" }).storeDir, "/gnu/store");
+  });
+  it("storePath", async function () {
+    assert_eq(
+      await sblti.storePath("/nix/store/abc-foo"),
+      "/nix/store/abc-foo"
+    );
+    try {
+      console.log(await sblti.storePath("/tmp/abc-foo"));
+      assert(false, "unreachable");
+    } catch (e) {
+      assert(e instanceof NixEvalError, "error kind");
+    }
+  });
+  it("placeholder", async function () {
+    assert_eq(
+      await sblti.placeholder("out"),
+      "/1rz4g4znpzjwh1xymhjpm42vipw92pr73vdgl6xs1hycac8kf2n9"
+    );
+  });
+});
+
+it("readFile", async function () {
+  let files = { "/nix/store/abc-foo": "foo", "/tmp/bar": "bar" };
+  let rblti = initRtDep({ readFile: async (path) => files[path] });
+  assert_eq(await rblti.readFile("/nix/store/abc-foo"), "foo", "store path");
+  assert_eq(await rblti.readFile(PLazy.from(async () => "/tmp/bar")), "bar");
+  try {
+    console.log(await xblti.readFile("/tmp/bar"));
+    assert(false, "unreachable");
+  } catch (e) {
+    assert(e instanceof NixEvalError, "error kind");
+  }
+});
+
+it("trace", async function () {
+  let msgs = [];
+  let tblti = initRtDep({ trace: (msg) => msgs.push(msg) });
+  assert_eq(await tblti.trace(PLazy.from(async () => "msg"))(42), 42);
+  assert_eq(msgs, ["msg"], "messages");
+});
+
+describe("genericClosure", function () {
+  it("should collapse duplicate integer keys", async function () {
+    let res = await xblti.genericClosure({
+      startSet: [{ key: 1n }, { key: 1n }],
+      operator: async (item) => {
+        let k = (await (await item).key) + 1n;
+        return k < 4n ? [{ key: k }, { key: 1n }] : [];
+      },
+    });
+    assert_eq(
+      res.map((i) => i.key),
+      [1n, 2n, 3n],
+      "keys"
+    );
+  });
+  it("should compare keys structurally", async function () {
+    let drv = (name) => ({ type: "derivation", name });
+    let res = await xblti.genericClosure({
+      startSet: [{ key: drv("a") }],
+      operator: async (item) => [{ key: drv("a") }, { key: drv("b") }],
+    });
+    assert_eq(res.length, 2, "length");
+  });
+});
+
+it("concatMap", async function () {
+  let res = await xblti.concatMap(PLazy.from(async () => async (x) => [x, x]))([
+    1n,
+    PLazy.from(async () => {
+      throw new NixEvalError("z");
+    }),
+  ]);
+  assert_eq(res.length, 4, "length");
+  assert_eq(await res[1], 1n, "element");
+});
+
+describe("toJSON", function () {
+  it("should force and sort attrsets", async function () {
+    let aset = {
+      b: PLazy.from(async () => [1n, 1.5, 2.0, null]),
+      a: { d: true, c: "x" },
+    };
+    assert_eq(
+      await xblti.toJSON(aset),
+      '{"a":{"c":"x","d":true},"b":[1,1.5,2.0,null]}',
+      "(1)"
+    );
+    assert_eq(
+      await xblti.fromJSON(await xblti.toJSON(aset)),
+      { a: { c: "x", d: true }, b: [1n, 1.5, 2.0, null] },
+      "(2)"
+    );
+  });
+  it("should reject non-finite floats", async function () {
+    try {
+      await xblti.toJSON([1e308 * 10]);
+      assert(false, "unreachable");
+    } catch (e) {
+      assert(e instanceof NixEvalError, "error kind");
+      assert_eq(e.message, "toJSON: cannot convert Infinity to JSON", "message");
+    }
+  });
+  it("should serialize derivations as their outPath", async function () {
+    assert_eq(
+      await xblti.toJSON({ outPath: "/nix/store/x", a: 1n }),
+      '"/nix/store/x"',
+      "(1)"
+    );
+  });
+});
+
+it("concatStringsSep", async function () {
+  assert_eq(await xblti.concatStringsSep("-")(["a", "b"]), "a-b", "join");
+  try {
+    await xblti.concatStringsSep(1n)(["a"]);
+    assert(false, "unreachable");
+  } catch (e) {
+    assert(e instanceof TypeError, "error kind");
+    assert_eq(e.message, "value is bigint while a string was expected", "message");
+  }
+});
+
+it("elemAt", async function () {
+  assert_eq(await xblti.elemAt([1n, 2n, 3n])(1n), 2n, "bigint index");
+  for (const [n, kind] of [
+    [-1n, RangeError],
+    [3n, RangeError],
+    [1.5, TypeError],
+    [1.0, TypeError],
+  ]) {
+    try {
+      await xblti.elemAt([1, 2, 3])(n);
+      assert(false, "unreachable");
+    } catch (e) {
+      assert(e instanceof kind, "error kind for " + n);
+    }
+  }
+});
+
+it("getAttr/hasAttr", async function () {
+  let aset = { ab: 1 };
+  assert_eq(await xblti.getAttr(PLazy.from(async () => "ab"))(aset), 1);
+  assert_eq(await xblti.hasAttr("x")({}), false, "hasAttr");
+  assert_eq(await xblti.hasAttr("toString")({}), false, "prototype");
+  try {
+    await xblti.getAttr("b")(aset);
+    assert(false, "unreachable");
+  } catch (e) {
+    assert(e instanceof NixEvalError, "error kind");
+    assert_eq(e.message, "attribute 'b' missing", "message");
+  }
+});
+
+it("head/tail of empty list", async function () {
+  for (const f of ["head", "tail"]) {
+    try {
+      await xblti[f]([]);
+      assert(false, "unreachable");
+    } catch (e) {
+      assert(e instanceof RangeError, "error kind for " + f);
+      assert_eq(
+        e.message,
+        "builtins." + f + " called on empty list",
+        "message"
+      );
+    }
+  }
+});
+
+it("intersectAttrs", async function () {
+  let tmp = await xblti.intersectAttrs({ a: 1, c: 2 })({
+    a: 3,
+    b: 4,
+    c: PLazy.from(async () => {
+      throw new NixEvalError("c");
+    }),
+  });
+  assert_eq(Object.keys(tmp), ["a", "c"], "keys");
+  assert_eq(await tmp.a, 3, "value from the second set");
+});
+
+it("attrNames/attrValues", async function () {
+  let aset = { b: 2, "a+": 3, a: 1 };
+  assert_eq(await xblti.attrNames(aset), ["a", "a+", "b"], "names");
+  assert_eq(await xblti.attrValues(aset), [1, 3, 2], "values");
+});
+
+describe("type predicates", function () {
+  let kinds = {
+    int: 1n,
+    float: 1.5,
+    string: "a",
+    bool: true,
+    list: [1n],
+    set: { a: 1n },
+    null: null,
+    lambda: async (x) => x,
+    path: new NixPath("/foo"),
+  };
+  let preds = {
+    isInt: "int",
+    isFloat: "float",
+    isString: "string",
+    isBool: "bool",
+    isList: "list",
+    isAttrs: "set",
+    isNull: "null",
+    isFunction: "lambda",
+    isPath: "path",
+  };
+  for (const [pred, kind] of Object.entries(preds)) {
+    it(pred, async function () {
+      for (const [k, v] of Object.entries(kinds)) {
+        assert_eq(await xblti[pred](v), k === kind, k);
+        assert_eq(
+          await xblti[pred](PLazy.from(async () => v)),
+          k === kind,
+          k + " (thunk)"
+        );
+      }
+    });
+  }
+  it("typeOf", async function () {
+    for (const [k, v] of Object.entries(kinds)) {
+      assert_eq(await xblti.typeOf(v), k, k);
+    }
+    // integral floats stay floats
+    assert_eq(await xblti.typeOf(1.0), "float", "integral float");
+    assert_eq(await xblti.isFloat(1.0), true, "isFloat (integral)");
+    assert_eq(await xblti.isInt(1.0), false, "isInt (integral float)");
+  });
+});
+
+describe("functions", function () {
+  let curried = async (a) => async (b) => a;
+  it("isFunction", async function () {
+    assert_eq(await xblti.isFunction(curried), true, "(1)");
+    assert_eq(await xblti.isFunction(curried(1)), true, "(2)");
+    assert_eq(await xblti.isFunction(1), false, "(3)");
+  });
+  it("functionArgs", async function () {
+    assert_eq(await xblti.functionArgs(curried), {}, "plain");
+    assert_eq(
+      await xblti.functionArgs(
+        setFunctionArgs({ x: false, y: true }, async (nixBound) => 1)
+      ),
+      { x: false, y: true },
+      "pattern"
+    );
+    assert_eq(await xblti.functionArgs(xblti.map), {}, "builtin");
+    assert_eq(await xblti.functionArgs(xblti["foldl'"](curried)), {}, "curried builtin");
+  });
+});
+
+describe("string context", function () {
+  const drv = "/nix/store/zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz-foo.drv";
+  const out = "/nix/store/zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz-foo";
+  let mkOut = () => xblti.appendContext(out)({ [drv]: { outputs: ["out"] } });
+
+  it("plain strings have no context", async function () {
+    assert_eq(await xblti.hasContext("a"), false, "hasContext");
+    assert_eq(await xblti.getContext("a"), {}, "getContext");
+  });
+  it("appendContext + getContext", async function () {
+    let s = await mkOut();
+    assert(s instanceof NixStringWithContext, "instance");
+    assert_eq(await xblti.hasContext(s), true, "hasContext");
+    assert_eq(
+      await xblti.getContext(s),
+      { [drv]: { outputs: ["out"] } },
+      "getContext"
+    );
+    assert_eq(await xblti.typeOf(s), "string", "typeOf");
+    assert_eq(await nixOp.Equal(s, out), true, "equality ignores context");
+  });
+  it("is propagated by concatenation", async function () {
+    let s = await nixOp.Add(mkOut(), "/bin");
+    assert_eq(s.valueOf(), out + "/bin", "value");
+    assert_eq(await xblti.hasContext(s), true, "+");
+    let t = await xblti.concatStringsSep(" ")(["a", mkOut()]);
+    assert_eq(await xblti.hasContext(t), true, "concatStringsSep");
+    assert_eq(await xblti.hasContext(await xblti.toString(mkOut())), true, "toString");
+  });
+  it("can be discarded", async function () {
+    let s = await xblti.unsafeDiscardStringContext(mkOut());
+    assert_eq(s, out, "value");
+    assert_eq(await xblti.hasContext(s), false, "hasContext");
+    let t = await xblti.unsafeDiscardOutputDependency(
+      xblti.appendContext(drv)({ [drv]: { allOutputs: true } })
+    );
+    assert_eq(await xblti.getContext(t), { [drv]: { path: true } }, "allOutputs");
+  });
 });
@@ -1,17 +1,33 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later
 
 import * as _ from "lodash-es";
-import assert from "webassert";
 import PLazy from "p-lazy";
 export { default as PLazy } from "p-lazy";
 
 export const API_VERSION = 0;
 
+// `abort` is not catchable via `tryEval`, unlike `throw` and failed assertions
 export class NixAbortError extends Error {}
 export class NixEvalError extends Error {}
 
-// TODO: add class for StringWithContext, although that might be unnecessary,
-// because we don't serialize derivations before submit...
+// paths are strings which are marked as such, `nixRt.export` should return these
+// to make paths distinguishable from strings (e.g. for `typeOf` and `isPath`)
+export class NixPath extends String {}
+
+// string context: strings which refer to store paths (e.g. the `outPath` of a
+// derivation) carry the set of these paths along. Such strings are represented as
+// `NixStringWithContext`, plain JS strings have an empty context. The context maps
+// store paths to `{ path?: true, allOutputs?: true, outputs?: string[] }`,
+// the same shape `builtins.getContext` returns. It is propagated (as union) by
+// `+` and `concatStringsSep`; string interpolation (emitted as JS `+`) and
+// all other string builtins currently discard it.
+export class NixStringWithContext extends String {
+  context: object;
+  constructor(s: string, context: object) {
+    super(s);
+    this.context = context;
+  }
+}
 
 type MaybePromise<T> = T | Promise<T>;
 
@@ -56,17 +72,43 @@ const otyforce = (objty, ax) =>
     }
     return val;
   };
-const discardStringContext = (s) => s;
+const discardStringContext = (s) =>
+  s instanceof NixStringWithContext ? s.valueOf() : s;
+const getStringContext = (s) =>
+  s instanceof NixStringWithContext ? s.context : {};
+const mergeStringContexts = (...ctxs: object[]): object => {
+  let ret = Object.create(null);
+  for (const ctx of ctxs) {
+    for (const [path, ent] of Object.entries(ctx)) {
+      let dst = ret[path] || (ret[path] = {});
+      if (ent.path) dst.path = true;
+      if (ent.allOutputs) dst.allOutputs = true;
+      if (ent.outputs) {
+        dst.outputs = (dst.outputs || []).concat(ent.outputs).filter(onlyUnique);
+        dst.outputs.sort();
+      }
+    }
+  }
+  return ret;
+};
+// only keeps the context object if it's non-empty
+const mkStringWithContext = (s: string, ctx: object) =>
+  Object.keys(ctx).length ? new NixStringWithContext(s, ctx) : s;
 const tyforce_string = natyforce(String, "string", "a");
 const tyforce_number = (val) =>
   typeof val === "bigint" ? val : natyforce(Number, "number", "a")(val);
+const tyforce_int = natyforce(BigInt, "bigint", "a");
 const tyforce_list = otyforce(Array, "an");
 
 const isnaty = (objty, natty) => (val) =>
   val instanceof objty || typeof val === natty;
 const isBool = isnaty(Boolean, "boolean");
 const isNumber = isnaty(Number, "number");
-const isString = isnaty(String, "string");
+const isString = (val) =>
+  !(val instanceof NixPath) && isnaty(String, "string")(val);
+// integers are represented as `bigint`, floats as `number`
+const isInteger = (val) => typeof val === "bigint";
+const isNumeric = (val) => isInteger(val) || isNumber(val);
 
 // the assignment ensures that future assignments won't currupt the prototype
 export const fixObjectProto = (...objs) =>
@@ -81,6 +123,15 @@ export const allKeys = Symbol("__all__");
 // without the proxy wrapper.
 export const extractScope = Symbol("__dict__");
 
+// used to store the formals of pattern lambdas,
+// mapping each argument name to whether it has a default value
+export const lambdaArgs = Symbol("__functionArgs__");
+
+export function setFunctionArgs(args: object, f: Function): Function {
+  Object.defineProperty(f, lambdaArgs, { value: fixObjectProto(args) });
+  return f;
+}
+
 export function mkScope(orig?: null | object): object {
   if (orig === undefined) {
     // "Object prototype may only be an Object or null"
@@ -171,11 +222,128 @@ export function mkScopeWith(...objs: object[]): object {
   );
 }
 
-const splitVersion = (s) =>
-  s
-    .split(/[^A-Za-z0-9]/)
-    .map((x) => x.split(/([A-Za-z]+|[0-9]+)/).filter((elem, idx) => idx % 2))
-    .flat();
+// ref: libutil/hash.cc printHash32
+const nixBase32Chars = "0123456789abcdfghijklmnpqrsvwxyz";
+const nixBase32 = (hash: Uint8Array): string => {
+  let ret = "";
+  for (let n = Math.floor((hash.length * 8 - 1) / 5); n >= 0; n--) {
+    const b = n * 5;
+    const i = Math.floor(b / 8);
+    const j = b % 8;
+    const c =
+      (hash[i] >> j) | (i >= hash.length - 1 ? 0 : hash[i + 1] << (8 - j));
+    ret += nixBase32Chars[c & 0x1f];
+  }
+  return ret;
+};
+
+// POSIX character classes, only valid inside of bracket expressions
+const posixClasses: { [key: string]: string } = {
+  alnum: "0-9A-Za-z",
+  alpha: "A-Za-z",
+  blank: " \\t",
+  cntrl: "\\x00-\\x1f\\x7f",
+  digit: "0-9",
+  graph: "\\x21-\\x7e",
+  lower: "a-z",
+  print: "\\x20-\\x7e",
+  punct: "!-\\/:-@\\[-`{-~",
+  space: " \\t\\n\\v\\f\\r",
+  upper: "A-Z",
+  xdigit: "0-9A-Fa-f",
+};
+
+// Nix uses POSIX extended regexes, which we translate into JS regexes:
+// bracket expressions (including character classes like `[[:alpha:]]`)
+// are rewritten, because backslashes and brackets are literal inside of them,
+// everything else (e.g. `\.`, groups, alternatives) is passed through.
+// NOTE: JS alternatives are leftmost-first instead of leftmost-longest,
+// which might result in different captures for ambiguous patterns.
+function posixToJsRegex(re: string): string {
+  let ret = "";
+  let i = 0;
+  while (i < re.length) {
+    if (re[i] === "\\") {
+      ret += re.slice(i, i + 2);
+      i += 2;
+    } else if (re[i] === "[") {
+      ret += "[";
+      i++;
+      if (re[i] === "^") {
+        ret += "^";
+        i++;
+      }
+      // a leading `]` is literal
+      if (re[i] === "]") {
+        ret += "\\]";
+        i++;
+      }
+      while (i < re.length && re[i] !== "]") {
+        const cls = /^\[:([a-z]+):\]/.exec(re.slice(i));
+        if (cls !== null) {
+          if (!Object.prototype.hasOwnProperty.call(posixClasses, cls[1])) {
+            throw new NixEvalError(
+              "invalid character class '" + cls[0] + "' in regex '" + re + "'"
+            );
+          }
+          ret += posixClasses[cls[1]];
+          i += cls[0].length;
+        } else {
+          ret += re[i] === "\\" || re[i] === "[" ? "\\" + re[i] : re[i];
+          i++;
+        }
+      }
+      if (i >= re.length) {
+        throw new NixEvalError(
+          "unterminated bracket expression in regex '" + re + "'"
+        );
+      }
+      ret += "]";
+      i++;
+    } else {
+      ret += re[i];
+      i++;
+    }
+  }
+  return ret;
+}
+
+const regexCache: Map<string, RegExp> = new Map();
+
+// `match` needs the regex to match the whole string, `split` searches for it
+function compileRegex(re: string, whole: boolean): RegExp {
+  const key = (whole ? "m" : "s") + re;
+  let ret = regexCache.get(key);
+  if (ret === undefined) {
+    const src = posixToJsRegex(re);
+    ret = whole ? new RegExp("^(?:" + src + ")$") : new RegExp(src, "g");
+    regexCache.set(key, ret);
+  }
+  return ret;
+}
+
+// unmatched groups are `null` in Nix
+const regexGroups = (m: RegExpExecArray): (string | null)[] =>
+  m.slice(1).map((x) => (x === undefined ? null : x));
+
+// like Nix: `.` and `-` are separators (consecutive ones are skipped),
+// a component is a maximal run of either digits or other characters
+const splitVersion = (s: string): string[] =>
+  s.match(/[0-9]+|[^0-9.-]+/g) || [];
+
+// mirrors `componentsLT` from Nix; missing components compare as ""
+function versionComponentLess(a: string, b: string): boolean {
+  const ina = /^[0-9]+$/.test(a);
+  const inb = /^[0-9]+$/.test(b);
+  if (ina && inb) return parseInt(a) < parseInt(b);
+  if (a === "" && inb) return true;
+  if (a === "pre" && b !== "pre") return true;
+  if (b === "pre") return false;
+  // `2.3a` < `2.3.1`
+  if (inb) return true;
+  if (ina) return false;
+  return a < b;
+}
 
 export async function orDefault<T>(
   selopf: T | PLazy<T>,
@@ -206,6 +374,11 @@ function binop_helper<T, R>(fname: string, f: (a: T, b: T) => R) {
   return async function (a: MaybePromise<T>, b: MaybePromise<T>): Promise<R> {
     a = await a;
     b = await b;
+    // ints and floats can be mixed, the result is a float then
+    if (typeof a !== typeof b && isNumeric(a) && isNumeric(b)) {
+      a = Number(a) as any as T;
+      b = Number(b) as any as T;
+    }
     let ta = typeof a;
     let tb = typeof b;
     if (ta === tb) {
@@ -231,24 +404,45 @@ function req_type<T>(fname: string, x: T, xptype: string): void {
   }
 }
 
-function req_number<T>(fname: string, x: T, y: T): [number, number] {
-  req_type(fname, x, "number");
-  return [x as any as number, y as any as number];
+// both arguments have the same type (see `binop_helper`), `bigint` or `number`
+function req_number<T>(fname: string, x: T, y: T): [any, any] {
+  if (!isInteger(x)) {
+    req_type(fname, x, "number");
+  }
+  return [x, y];
 }
 
-function req_boolean<T>(fname: string, x: T, y: T): [boolean, boolean] {
+async function bool_helper(
+  fname: string,
+  x: MaybePromise<any>
+): Promise<boolean> {
+  x = await x;
   req_type(fname, x, "boolean");
-  return [x as any as boolean, y as any as boolean];
+  return x;
 }
 
 const isAttrs = (e: any): boolean =>
   typeof e === "object" &&
-  !(e instanceof Boolean || e instanceof Number || e instanceof String);
+  e !== null &&
+  !(
+    e instanceof Array ||
+    e instanceof Boolean ||
+    e instanceof Number ||
+    e instanceof String
+  );
 
-const deepSeq_helper = async (e) => {
+// already visited values are skipped, self-referential values
+// (e.g. `let x = { self = x; }; in x`) would recurse infinitely otherwise
+const deepSeq_helper = async (e, seen: Set<object> = new Set()) => {
   e = await e;
-  if (isAttrs(e)) {
-    await Promise.all(e.map((i) => deepSeq_helper(i)));
+  if (typeof e !== "object" || e === null || seen.has(e)) {
+    return;
+  }
+  seen.add(e);
+  if (e instanceof Array) {
+    await Promise.all(e.map((i) => deepSeq_helper(i, seen)));
+  } else if (isAttrs(e)) {
+    await Promise.all(Object.values(e).map((i) => deepSeq_helper(i, seen)));
   }
 };
 
@@ -318,42 +512,90 @@ export async function sortAsyncList<T>(
     .map(([i, ix]: [T, number]): T => i);
 }
 
-/* @preserve
-anti-prototype pollution filter taken from npm package 'no-pollution'
-source: https://github.com/DaniAkash/no-pollution/blob/3bfe3f419d49acd1ab157c7b9655161c4942fedd/index.js
-Copyright (c) 2019-present DaniAkash
-SPDX-License-Identifier: MIT
-
-Permission is hereby granted, free of charge, to any person obtaining a copy
-of this software and associated documentation files (the "Software"), to deal
-in the Software without restriction, including without limitation the rights
-to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
-copies of the Software, and to permit persons to whom the Software is
-furnished to do so, subject to the following conditions:
-
-The above copyright notice and this permission notice shall be included in all
-copies or substantial portions of the Software.
-
-THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
-IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
-FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
-AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
-LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
-OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
-SOFTWARE.
-*/
-const anti_pollution = (e: string): object =>
-  fixObjectProto(
-    JSON.parse(
-      e.replace(
-        new RegExp(
-          "(_|\\\\_|[\\\\u05fFx]+){2}proto(_|\\\\_|[\\\\u05fFx]+){2}(?=[\"']+[\\s:]+[{\"'])",
-          "g"
-        ),
-        "__pollutants__"
-      )
-    )
-  );
+// `JSON.parse` can't distinguish `1` from `1.0`, thus a minimal parser:
+// integers become `bigint`s, objects get no prototype (see `fixObjectProto`)
+function nixFromJSON(s: string): any {
+  let pos = 0;
+  const fail = (): never => {
+    throw new NixEvalError("fromJSON: syntax error at offset " + pos);
+  };
+  const token = (re: RegExp): string => {
+    re.lastIndex = pos;
+    const m = re.exec(s);
+    if (m === null) return fail();
+    pos += m[0].length;
+    return m[0];
+  };
+  const skipWs = () => token(/[ \t\n\r]*/y);
+  const str = (): string =>
+    JSON.parse(token(/"(?:[^"\\\u0000-\u001f]|\\(?:["\\/bfnrt]|u[0-9a-fA-F]{4}))*"/y));
+  const value = (): any => {
+    skipWs();
+    switch (s[pos]) {
+      case "{": {
+        pos++;
+        let ret = Object.create(null);
+        skipWs();
+        if (s[pos] === "}") {
+          pos++;
+          return ret;
+        }
+        while (true) {
+          skipWs();
+          const key = str();
+          skipWs();
+          if (s[pos++] !== ":") fail();
+          ret[key] = value();
+          skipWs();
+          const sep = s[pos++];
+          if (sep === "}") return ret;
+          if (sep !== ",") fail();
+        }
+      }
+      case "[": {
+        pos++;
+        let ret: any[] = [];
+        skipWs();
+        if (s[pos] === "]") {
+          pos++;
+          return ret;
+        }
+        while (true) {
+          ret.push(value());
+          skipWs();
+          const sep = s[pos++];
+          if (sep === "]") return ret;
+          if (sep !== ",") fail();
+        }
+      }
+      case '"':
+        return str();
+    }
+    for (const [lit, v] of [["true", true], ["false", false], ["null", null]]) {
+      if (s.startsWith(lit as string, pos)) {
+        pos += (lit as string).length;
+        return v;
+      }
+    }
+    const num = token(/-?(?:0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?/y);
+    return /^-?[0-9]+$/.test(num) ? BigInt(num) : Number(num);
+  };
+  const ret = value();
+  skipWs();
+  if (pos < s.length) fail();
+  return ret;
+}
+
+// floats are formatted like C's `%f` (as Nix does)
+function floatToString(x: number): string {
+  if (Number.isNaN(x)) return "nan";
+  if (!Number.isFinite(x)) return x < 0 ? "-inf" : "inf";
+  const abs = Math.abs(x);
+  // `toFixed` switches to an exponent at 1e21, `%f` doesn't
+  const ret =
+    abs < 1e21 ? abs.toFixed(6) : BigInt(abs).toString() + ".000000";
+  return (x < 0 || Object.is(x, -0) ? "-" : "") + ret;
+}
 
 const nixToStringHandler = {
   object: async function (x: object): Promise<string> {
@@ -370,13 +612,14 @@ const nixToStringHandler = {
   },
   string: (x) => x,
   bigint: (x) => x.toString(),
-  number: (x) => x.toString(),
+  number: floatToString,
   boolean: (x) => (x ? "1" : ""),
 };
 
-async function nixToString(x: any): Promise<string> {
+async function nixToString(x: any): Promise<string | NixStringWithContext> {
   x = await x;
   if (x === null || x === undefined) return "";
+  if (x instanceof NixStringWithContext) return x;
   if (typeof x === "object" && "valueOf" in x) x = x.valueOf();
   if (nixToStringHandler.hasOwnProperty(typeof x)) {
     return await nixToStringHandler[typeof x](x);
@@ -384,14 +627,92 @@ async function nixToString(x: any): Promise<string> {
   throw new NixEvalError("nixToString: unserializable type " + typeof x);
 }
 
+// formats finite floats like Nix' `toJSON` does: the shortest round-tripping
+// digits, integral values get a `.0` suffix and an exponent is used
+// outside of [1e-4, 1e15)
+function floatToJSON(x: number): string {
+  if (x === 0) return Object.is(x, -0) ? "-0.0" : "0.0";
+  const [mant, exp] = Math.abs(x).toExponential().split("e");
+  const digits = mant.replace(".", "");
+  const k = digits.length;
+  // the position of the decimal point, relative to the first digit
+  const n = Number(exp) + 1;
+  let ret: string;
+  if (k <= n && n <= 15) {
+    ret = digits + "0".repeat(n - k) + ".0";
+  } else if (0 < n && n <= 15) {
+    ret = digits.slice(0, n) + "." + digits.slice(n);
+  } else if (-4 < n && n <= 0) {
+    ret = "0." + "0".repeat(-n) + digits;
+  } else {
+    const e = n - 1;
+    ret =
+      digits[0] +
+      (k > 1 ? "." + digits.slice(1) : "") +
+      (e < 0 ? "e-" : "e+") +
+      String(Math.abs(e)).padStart(2, "0");
+  }
+  return (x < 0 ? "-" : "") + ret;
+}
+
+// forces the value completely; attrsets are serialized with sorted keys,
+// or as their `outPath` (e.g. derivations), `bigint`s without precision loss
+async function nixToJSON(x: any): Promise<string> {
+  x = await x;
+  if (x === null || x === undefined) return "null";
+  if (x instanceof Array) {
+    let parts: string[] = [];
+    for (const i of x) parts.push(await nixToJSON(i));
+    return "[" + parts.join(",") + "]";
+  }
+  if (x instanceof String) x = x.valueOf();
+  switch (typeof x) {
+    case "bigint":
+      return x.toString();
+    case "number":
+      // `JSON.stringify` would emit `null`, Nix throws
+      if (!Number.isFinite(x))
+        throw new NixEvalError("toJSON: cannot convert " + x + " to JSON");
+      return floatToJSON(x);
+    case "boolean":
+    case "string":
+      return JSON.stringify(x);
+    case "function":
+      throw new NixEvalError("toJSON: cannot convert a function to JSON");
+  }
+  if ("outPath" in x) return await nixToJSON(x.outPath);
+  let parts: string[] = [];
+  for (const k of Object.keys(x).sort()) {
+    parts.push(JSON.stringify(k) + ":" + (await nixToJSON(x[k])));
+  }
+  return "{" + parts.join(",") + "}";
+}
+
+// maps `typeof` results to Nix type names
 const nixTypeOf = {
   bigint: "int",
+  number: "float",
   boolean: "bool",
   function: "lambda",
-  number: "float",
   object: "set",
+  string: "string",
 };
 
+const addNumbers = binop_helper("builtins.add", function <T>(a: T, b: T) {
+  let [c, d] = req_number("builtins.add", a, b);
+  return c + d;
+});
+
+const addPlain = binop_helper("+", function <T>(a: T, b: T) {
+  if (isNumeric(a)) {
+    return (a as any) + (b as any);
+  } else if (typeof a === "string") {
+    return a + (b as any as string);
+  } else {
+    throw TypeError("operator +: invalid input type (" + typeof a + ")");
+  }
+});
+
 // operators
 export const nixOp = {
   u_Invert: async (a) => !(await a),
@@ -426,26 +747,24 @@ export const nixOp = {
       }
     }
   },
-  _lambdaA2chk: async function (
-    attrs: object,
-    key: string,
-    fallback?: Promise<any>
-  ): Promise<any> {
-    let tmp = await attrs[key];
+  // this must not force anything: the argument and the fallback
+  // only get evaluated once they are actually used.
+  _lambdaA2chk: function (attrs: object, key: string, fallback?: PLazy<any>) {
+    let tmp = attrs[key];
     if (tmp === undefined) {
       if (fallback === undefined) {
         // TODO: adjust error message to what Nix currently issues.
         throw new NixEvalError(
-          "Attrset element " + key + "missing at lambda call"
+          "Attrset element " + key + " missing at lambda call"
         );
-      } else {
-        tmp = await fallback;
       }
+      tmp = fallback;
     }
     return tmp;
   },
+  // only the lists themselves are forced, their elements stay untouched
   Concat: binop_helper("operator ++", function (a: any[], b: any[]) {
-    if (typeof a !== "object") {
+    if (!(a instanceof Array && b instanceof Array)) {
       throw TypeError("operator ++: invalid input type (" + typeof a + ")");
     }
     return a.concat(b);
@@ -457,15 +776,17 @@ export const nixOp = {
     }
     return fixObjectProto({}, a, b);
   }),
-  Add: binop_helper("+", function <T>(a: T, b: T) {
-    if (typeof a === "number") {
-      return a + (b as any as number);
-    } else if (typeof a === "string") {
-      return a + (b as any as string);
-    } else {
-      throw TypeError("operator +: invalid input type (" + typeof a + ")");
+  Add: async (a, b) => {
+    a = await a;
+    b = await b;
+    if (a instanceof NixStringWithContext || b instanceof NixStringWithContext) {
+      return mkStringWithContext(
+        tyforce_string(a) + tyforce_string(b),
+        mergeStringContexts(getStringContext(a), getStringContext(b))
+      );
     }
-  }),
+    return addPlain(a, b);
+  },
   Sub: binop_helper("-", function <T>(a: T, b: T) {
     let [c, d] = req_number("-", a, b);
     return c - d;
@@ -474,6 +795,7 @@ export const nixOp = {
     let [c, d] = req_number("*", a, b);
     return c * d;
   }),
+  // `bigint` division truncates, like integer division in Nix
   Div: binop_helper("/", function <T>(a: T, b: T) {
     let [c, d] = req_number("/", a, b);
     if (!d) {
@@ -481,20 +803,24 @@ export const nixOp = {
     }
     return c / d;
   }),
-  And: binop_helper("&&", function <T>(a: T, b: T) {
-    let [c, d] = req_boolean("&&", a, b);
-    return c && d;
-  }),
-  Implication: binop_helper("->", function <T>(a: T, b: T) {
-    req_boolean("->", a, b);
-    return !a || b;
-  }),
-  Or: binop_helper("||", function <T>(a: T, b: T) {
-    req_boolean("||", a, b);
-    return a || b;
-  }),
-  Equal: async (a, b) => _.isEqual(await a, await b),
-  NotEqual: async (a, b) => !_.isEqual(await a, await b),
+  // the rhs of these is only forced if the lhs doesn't decide the result
+  And: async (a, b) =>
+    (await bool_helper("&&", a)) && (await bool_helper("&&", b)),
+  Implication: async (a, b) =>
+    !(await bool_helper("->", a)) || (await bool_helper("->", b)),
+  Or: async (a, b) =>
+    (await bool_helper("||", a)) || (await bool_helper("||", b)),
+  // the string context doesn't influence equality,
+  // ints and floats are compared by value (`1 == 1.0`)
+  Equal: async (a, b) =>
+    _.isEqualWith(await a, await b, (x, y) => {
+      if (x instanceof NixStringWithContext || y instanceof NixStringWithContext)
+        return discardStringContext(x) === discardStringContext(y);
+      if (isInteger(x) || isInteger(y))
+        return isNumeric(x) && isNumeric(y) && x == y;
+      return undefined;
+    }),
+  NotEqual: async (a, b) => !(await nixOp.Equal(a, b)),
   Less: binop_helper("<", function <T>(a: T, b: T) {
     req_number("<", a, b);
     return a < b;
@@ -517,28 +843,35 @@ const IndepBltis = {
   abort: async (s) => {
     throw new NixAbortError(tyforce_string(await s));
   },
-  add: (a) =>
-    async function (b) {
-      a = await a;
-      b = await b;
-      let ta = typeof a;
-      let tb = typeof b;
-      if (ta !== tb) {
-        throw TypeError(
-          "builtins.add: given types mismatch (" + ta + " != " + tb + ")"
-        );
-      } else if (ta === "number") {
-        return a + b;
-      } else {
-        throw TypeError(
-          "builtins.add: invalid input type (" + ta + "), expected (number)"
-        );
-      }
-    },
+  add: (a) => (b) => addNumbers(a, b),
   all: (pred) => async (list) =>
     (await Promise.all(tyforce_list(await list).map(pred))).every((x) => x),
   any: (pred) => async (list) =>
     (await Promise.all(tyforce_list(await list).map(pred))).some((x) => x),
+  // the context entries get merged into the existing context of the string
+  appendContext: (s) => async (ctx) => {
+    s = await s;
+    const str = tyforce_string(s);
+    ctx = await ctx;
+    let add = Object.create(null);
+    for (const path of Object.keys(ctx).sort()) {
+      const ent = await ctx[path];
+      let dst: any = {};
+      if (await ent.path) dst.path = true;
+      if (await ent.allOutputs) dst.allOutputs = true;
+      if (ent.outputs !== undefined) {
+        dst.outputs = (await resolveList(tyforce_list(await ent.outputs))).map(
+          tyforce_string
+        );
+      }
+      add[path] = dst;
+    }
+    return mkStringWithContext(
+      str,
+      mergeStringContexts(getStringContext(s), add)
+    );
+  },
+  // `condstr` is `line N: <condition>`, long conditions are abbreviated (`...`)
   assert: (condstr: string) => async (cond) => {
     if (typeof cond === "function") {
       // async functions are still functions
@@ -550,62 +883,72 @@ const IndepBltis = {
         "Assertion condition has wrong type (" + typeof cond2 + ")"
       );
     }
-    assert(cond2, condstr);
+    if (!cond2) {
+      throw new NixEvalError("assertion failed: " + condstr);
+    }
   },
+  // both are sorted by key, thus `attrValues` matches the order of `attrNames`
   attrNames: async (aset) => Object.keys(await aset).sort(),
-  attrValues: async (aset) =>
-    Object.entries(await aset)
+  attrValues: async (aset) => {
+    aset = await aset;
+    return Object.keys(aset)
       .sort()
-      .map((a) => a[1]),
+      .map((k) => aset[k]);
+  },
   baseNameOf: async (s) => _.last(tyforce_string(await s).split("/")),
   bitAnd: (v1) => async (v2) =>
-    tyforce_number(await v1) & tyforce_number(await v2),
+    tyforce_int(await v1) & tyforce_int(await v2),
   bitOr: (v1) => async (v2) =>
-    tyforce_number(await v1) | tyforce_number(await v2),
+    tyforce_int(await v1) | tyforce_int(await v2),
   catAttrs: (s) => async (list) => {
     const s2 = tyforce_string(await s);
     return (await resolveList(tyforce_list(await list)))
       .filter((aset) => Object.prototype.hasOwnProperty.call(aset, s2))
       .map((aset) => aset[s2]);
   },
-  ceil: async (n) => Math.ceil(tyforce_number(await n)),
+  ceil: async (n) => BigInt(Math.ceil(Number(tyforce_number(await n)))),
   compareVersions: (s1) => async (s2) => {
     let s1p = splitVersion(tyforce_string(await s1));
     let s2p = splitVersion(tyforce_string(await s2));
-    let ret = _.zip(s1p, s2p)
-      .map(([a, b]) => {
-        if (a === b) return 0;
-        const ina = a && a.match(/^[0-9]+$/g) !== null;
-        const inb = b && b.match(/^[0-9]+$/g) !== null;
-        if (ina && inb) {
-          const [pia, pib] = [parseInt(a), parseInt(b)];
-          return pia < pib ? -1 : pia == pib ? 0 : 1;
-        }
-        if ((a === "" || a === undefined) && inb) return -1;
-        if (ina && (b === "" || b === undefined)) return 1;
-        if (a === "pre" || (!ina && inb)) return -1;
-        if (b === "pre" || (ina && !inb)) return 1;
-        return a < b ? -1 : a == b ? 0 : 1;
-      })
-      .find((x) => x !== undefined && x !== 0);
-    return ret !== undefined ? ret : 0;
+    for (let i = 0; i < Math.max(s1p.length, s2p.length); i++) {
+      const a = i < s1p.length ? s1p[i] : "";
+      const b = i < s2p.length ? s2p[i] : "";
+      if (versionComponentLess(a, b)) return -1n;
+      if (versionComponentLess(b, a)) return 1n;
+    }
+    return 0n;
   },
+  // only the outer list and the inner lists themselves get forced,
+  // the elements of the inner lists stay lazy
   concatLists: async (lists) =>
     await transformAsyncList(
       lists,
       (x) => x,
-      (x) => x.flat()
-    ),
-  concatMap: (f) => async (lists) =>
-    await transformAsyncList(
-      lists,
-      (x) => x.map(f),
-      (x) => x.flat()
-    ),
-  concatStringsSep: (sep) => async (list) =>
-    (await resolveList(tyforce_list(await list))).join(
-      tyforce_string(await sep)
+      (x) => x.map(tyforce_list).flat()
     ),
+  // `f` gets applied to all (unforced) elements, because all resulting lists
+  // are needed for the flattening, but their elements stay lazy
+  concatMap: (f) => async (list) => {
+    const g = await f;
+    return await transformAsyncList(
+      list,
+      (x) => x.map((y) => g(y)),
+      (x) => x.map(tyforce_list).flat()
+    );
+  },
+  concatStringsSep: (sep) => async (list) => {
+    sep = await sep;
+    // validated even if the list has less than two elements, like in Nix
+    tyforce_string(sep);
+    const items = await resolveList(tyforce_list(await list));
+    return mkStringWithContext(
+      items.map(tyforce_string).join(tyforce_string(sep)),
+      mergeStringContexts(
+        ...(items.length > 1 ? [getStringContext(sep)] : []),
+        ...items.map(getStringContext)
+      )
+    );
+  },
   deepSeq: async (e1) => {
     await deepSeq_helper(e1);
     return (e2) => e2;
@@ -615,37 +958,87 @@ const IndepBltis = {
     tmp.pop();
     return tmp.join("/");
   },
-  div: (a) => async (b) => {
-    const bx = tyforce_number(await b);
-    // TODO: integer division?
-    if (!bx) {
-      throw RangeError("Division by zero");
-    }
-    return tyforce_number(await a) / bx;
-  },
+  div: (a) => (b) => nixOp.Div(a, b),
   elem: (x) => async (xs) =>
     (await Promise.all(tyforce_list(await xs))).includes(await x),
+  // the index gets forced and must be an integer within bounds,
+  // JS would return `undefined` for e.g. `-1` or `1.5`
   elemAt: (xs) => async (n) => {
-    let tmp = await tyforce_list(await xs)[tyforce_number(await n)];
-    if (tmp === undefined) {
-      throw RangeError("Index out of range");
+    const list = tyforce_list(await xs);
+    n = tyforce_number(await n);
+    if (!isInteger(n)) {
+      throw TypeError("builtins.elemAt: invalid index type (float), expected (int)");
     }
-    return tmp;
+    if (n < 0 || n >= list.length) {
+      throw RangeError("builtins.elemAt: list index " + n + " is out of bounds");
+    }
+    return await list[Number(n)];
   },
 
   // omitted: fetchGit, fetchTarball, fetchurl
   filter: (f) => async (list) =>
     await filterAsyncList(tyforce_list(await list), await f),
   // omitted: filterSource
-  floor: async (n) => Math.floor(tyforce_number(await n)),
+  floor: async (n) => BigInt(Math.floor(Number(tyforce_number(await n)))),
   "foldl'": (op) => (nul) => async (list) =>
     tyforce_list(await list).reduce(await op, nul),
-  fromJSON: async (e) => anti_pollution(tyforce_string(await e)),
+  fromJSON: async (e) => nixFromJSON(tyforce_string(await e)),
 
-  // TODO: functionArgs -- requires nix2js/lib.rs modification
+  functionArgs: async (f) => {
+    f = await f;
+    if (!(f instanceof Function)) {
+      throw TypeError(
+        "builtins.functionArgs: value is " +
+          typeof f +
+          " while a lambda was expected"
+      );
+    }
+    return fixObjectProto(f[lambdaArgs]);
+  },
 
+  // the elements are only generated once they are accessed
   genList: (gen_) => async (len) =>
-    Array({ length: tyforce_number(await len) }, (dummy, i) => gen_(i)),
+    Array.from({ length: Number(tyforce_int(await len)) }, (dummy, i) =>
+      PLazy.from(async () => (await gen_)(BigInt(i)))
+    ),
+  // processes the items breadth-first, deduplicating them
+  // via Nix equality on their `key` attribute
+  genericClosure: async (args) => {
+    args = await args;
+    const operator = await args.operator;
+    let work = [...tyforce_list(await args.startSet)];
+    let keys = [];
+    let ret = [];
+    while (work.length) {
+      const item = await work.shift();
+      const key = await item.key;
+      if (key === undefined) {
+        throw new NixEvalError(
+          "builtins.genericClosure: attribute 'key' required"
+        );
+      }
+      let seen = false;
+      for (const i of keys) {
+        if (await nixOp.Equal(i, key)) {
+          seen = true;
+          break;
+        }
+      }
+      if (seen) continue;
+      keys.push(key);
+      ret.push(item);
+      work.push(...tyforce_list(await operator(item)));
+    }
+    return ret;
+  },
+  getAttr: (s) => async (aset) => {
+    const key = tyforce_string(await s);
+    aset = await aset;
+    if (!Object.prototype.hasOwnProperty.call(aset, key)) {
+      throw new NixEvalError("attribute '" + key + "' missing");
+    }
+    return await aset[key];
+  },
   getEnv: async (s) => {
     if (typeof process === "undefined" || !process.hasOwnProperty('env'))
       return "";
@@ -656,8 +1049,23 @@ const IndepBltis = {
   },
   groupBy: (f) => async (list) => _.groupBy(tyforce_list(await list), await f),
 
+  getContext: async (s) => {
+    s = await s;
+    tyforce_string(s);
+    const ctx = getStringContext(s);
+    return fixObjectProto(
+      ...Object.keys(ctx)
+        .sort()
+        .map((path) => ({ [path]: fixObjectProto(ctx[path]) }))
+    );
+  },
   hasAttr: (s) => async (aset) =>
     Object.prototype.hasOwnProperty.call(await aset, tyforce_string(await s)),
+  hasContext: async (s) => {
+    s = await s;
+    tyforce_string(s);
+    return Object.keys(getStringContext(s)).length !== 0;
+  },
   // omitted: hashFile, hashString
   head: async (list) => {
     list = tyforce_list(await list);
@@ -670,29 +1078,37 @@ const IndepBltis = {
   // omitted: import
 
   // ref: https://stackoverflow.com/a/1885569
+  // iterates over the keys of `e1` (like Nix), the values are taken from `e2`
+  // (without forcing them); both arguments are forced
   intersectAttrs: (e1) => async (e2) => {
-    let e2k = Object.keys(await e2);
-    // "value => ... includes(value)" is necessary to avoid TypeErrors
-    return Object.keys(await e1)
-      .filter((value) => e2k.includes(value))
-      .filter(onlyUnique);
+    const [a, b] = [await e1, await e2];
+    if (!isAttrs(a) || !isAttrs(b)) {
+      throw TypeError("builtins.intersectAttrs: invalid input type, expected (set)");
+    }
+    let ret = Object.create(null);
+    for (const k of Object.keys(a)) {
+      if (Object.prototype.hasOwnProperty.call(b, k)) ret[k] = b[k];
+    }
+    return ret;
   },
 
+  // all type predicates force their argument first;
+  // integers are represented as `bigint`, floats as `number`
   isAttrs: async (e) => isAttrs(await e),
   isBool: async (e) => isBool(await e),
   isFloat: async (e) => isNumber(await e),
   isFunction: async (e) => (await e) instanceof Function,
-  isInt: async (e) => typeof (await e) === "bigint",
+  isInt: async (e) => isInteger(await e),
   isList: async (e) => (await e) instanceof Array,
 
   // DEPRECATED
   isNull: async (e) => (await e) === null,
 
-  // TODO: isPath
+  isPath: async (e) => (await e) instanceof NixPath,
 
   isString: async (e) => isString(await e),
 
-  length: async (e) => tyforce_list(await e).length,
+  length: async (e) => BigInt(tyforce_list(await e).length),
   lessThan: (e1) => async (e2) =>
     tyforce_number(await e1) < tyforce_number(await e2),
 
@@ -708,21 +1124,32 @@ const IndepBltis = {
       )
     ),
 
-  map: (f) => async (list) => tyforce_list(await list).map(await f),
+  map: (f) => async (list) => {
+    list = tyforce_list(await list);
+    f = await f;
+    return list.map((x) => PLazy.from(async () => f(x)));
+  },
   // ref: https://stackoverflow.com/a/14810722
+  // the key set is realized immediately, the values stay lazy
   mapAttrs: (f) => async (aset: MaybePromise<object>) =>
     fixObjectProto(
       Object.fromEntries(
         Object.entries(await aset).map(([k, v]) => [
           k,
-          (async (k_, v_) => await (await f(k))(v))(k, v),
+          PLazy.from(async () => await (await (await f)(k))(v)),
         ])
       )
     ),
 
-  // TODO: `match`, maybe via compiling the original `prim_match` to webassembly
+  // see `posixToJsRegex` for the supported regex flavor
+  match: (re) => async (s) => {
+    const m = compileRegex(tyforce_string(await re), true).exec(
+      tyforce_string(await s)
+    );
+    return m === null ? null : regexGroups(m);
+  },
 
-  mul: (a) => async (b) => tyforce_number(await a) * tyforce_number(await b),
+  mul: (a) => (b) => nixOp.Mul(a, b),
 
   parseDrvName: async (s) => {
     let [name, version] = tyforce_string(await s).split("-", 2);
@@ -734,10 +1161,11 @@ const IndepBltis = {
     return fixObjectProto({ right, wrong });
   },
 
-  // TODO: path, placeholder
+  // TODO: path
   // omitted: pathExists
-  // TODO: placeholder
-  // omitted: readDir, readFile
+  // placeholder: see initRtDep
+  // omitted: readDir
+  // readFile: see initRtDep
 
   removeAttrs: (aset) => async (list) => {
     // make sure that we don't override the original object
@@ -775,15 +1203,38 @@ const IndepBltis = {
 
   sort: (comp) => async (list) => sortAsyncList(list, await comp),
 
-  // TODO: `split`, see also: `match`
+  split: (re) => async (s) => {
+    const rx = compileRegex(tyforce_string(await re), false);
+    const str = tyforce_string(await s);
+    let ret: (string | (string | null)[])[] = [];
+    let last = 0;
+    let m: RegExpExecArray | null;
+    rx.lastIndex = 0;
+    while ((m = rx.exec(str)) !== null) {
+      ret.push(str.slice(last, m.index), regexGroups(m));
+      last = m.index + m[0].length;
+      // avoid getting stuck at empty matches
+      if (m[0] === "") rx.lastIndex++;
+    }
+    ret.push(str.slice(last));
+    return ret;
+  },
 
   splitVersion: async (s) => splitVersion(tyforce_string(await s)),
 
-  // omitted: storePath
+  storeDir: "/nix/store",
+
+  // storePath: see initRtDep
 
-  stringLength: async (s) => tyforce_string(await s).length,
+  stringLength: async (s) => BigInt(tyforce_string(await s).length),
 
-  tail: async (list) => tyforce_list(await list).slice(1),
+  tail: async (list) => {
+    list = tyforce_list(await list);
+    if (!list.length) {
+      throw RangeError("builtins.tail called on empty list");
+    }
+    return list.slice(1);
+  },
 
   throw: async (s) => {
     throw new NixEvalError(tyforce_string(await s));
@@ -792,18 +1243,21 @@ const IndepBltis = {
   // TODO: toFile, via store interaction or derivation; weird stuff
 
   // TODO: handle derivations
-  toJSON: async (x) => JSON.stringify(await x),
+  toJSON: nixToJSON,
 
   // omitted: toPath; also DEPRECATED
 
   // NOTE: we `await` in `nixToString`, because it recurses
+  // coercions: true -> "1", false/null -> "", int -> decimal,
+  // list -> space-separated coerced elements (see `nixToStringHandler`)
   toString: nixToString,
 
   // TODO: toXML
 
-  trace: (e1) => (e2) => {
-    console.debug(e1);
-    return e2;
+  // overridden by `initRtDep`, to use `nixRt.trace`
+  trace: (e1) => async (e2) => {
+    console.debug(await e1);
+    return await e2;
   },
 
   tryEval: async (e) => {
@@ -826,11 +1280,27 @@ const IndepBltis = {
     // need to differentiate this with `null` because of distinction via `isNull`,
     // and `isNull` deprecation.
     if (e === undefined) return "undefined";
+    if (e instanceof NixPath) return "path";
     if (typeof e === "object" && "valueOf" in e) e = e.valueOf();
     let ety = typeof e;
     if (ety === "object" && e instanceof Array) return "list";
     return nixTypeOf.hasOwnProperty(ety) ? nixTypeOf[ety] : ety;
   },
+
+  // `allOutputs` (the dependency on all outputs of a derivation) becomes a
+  // dependency on the `.drv` file itself
+  unsafeDiscardOutputDependency: async (s) => {
+    s = await s;
+    const str = tyforce_string(s);
+    const ctx = getStringContext(s);
+    let ret = Object.create(null);
+    for (const path of Object.keys(ctx)) {
+      let { allOutputs, ...ent } = ctx[path];
+      ret[path] = allOutputs ? { ...ent, path: true } : ent;
+    }
+    return mkStringWithContext(str, ret);
+  },
+  unsafeDiscardStringContext: async (s) => tyforce_string(await s),
 };
 
 export function initRtDep(nixRt) {
@@ -846,11 +1316,44 @@ export function initRtDep(nixRt) {
     "import",
     "pathExists",
     "readDir",
-    "readFile",
-    "storePath",
     "toPath",
   ]) {
     tmp[i] = nixRt[i];
   }
+  if (nixRt.storeDir !== undefined) {
+    tmp.storeDir = nixRt.storeDir;
+  }
+  // the embedder controls where the messages end up
+  if (nixRt.trace !== undefined) {
+    tmp.trace = (e1) => async (e2) => {
+      await nixRt.trace(await e1);
+      return await e2;
+    };
+  }
+  tmp.placeholder = async (output) => {
+    const hash = await (
+      await tmp.hashString("sha256")
+    )("nix-output:" + tyforce_string(await output));
+    const bytes = new Uint8Array(
+      hash.match(/../g).map((x: string) => parseInt(x, 16))
+    );
+    return "/" + nixBase32(bytes);
+  };
+  tmp.readFile = async (path) => {
+    if (nixRt.readFile === undefined) {
+      throw new NixEvalError("builtins.readFile: unsupported by the runtime");
+    }
+    return tyforce_string(await nixRt.readFile(tyforce_string(await path)));
+  };
+  tmp.storePath = async (path) => {
+    const p = tyforce_string(await path);
+    const pfx = tmp.storeDir + "/";
+    if (!p.startsWith(pfx) || !p.slice(pfx.length).split("/")[0]) {
+      throw new NixEvalError(
+        "path '" + p + "' is not in the Nix store (" + tmp.storeDir + ")"
+      );
+    }
+    return nixRt.storePath !== undefined ? await nixRt.storePath(p) : p;
+  };
   return tmp;
 }
@@ -1,4 +1,4 @@
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum IdentCateg {
     Literal(&'static str),
 
@@ -11,6 +11,12 @@ pub enum IdentCateg {
     // used for simple let..in stmts
     LetLetVar,
 
+    // like `LetLetVar`, but bound to an attrset literal with statically known keys
+    LetLetAttrs(std::rc::Rc<[String]>),
+
+    // key of a `LetLetAttrs` attrset, brought into scope via `with` (index into `vars`)
+    WithLetAttr(usize),
+
     // also used for recursive attrsets
     LetInScopeVar,
 
@@ -22,10 +28,42 @@ pub const NIX_BUILTINS_RT: &str = "nixBltiRT";
 pub const NIX_OPERATORS: &str = "nixOp";
 pub const NIX_EXTRACT_SCOPE: &str = "nixBlti.extractScope";
 pub const NIX_OR_DEFAULT: &str = "nixBlti.orDefault";
+pub const NIX_SET_FUNCTION_ARGS: &str = "nixBlti.setFunctionArgs";
 pub const NIX_RUNTIME: &str = "nixRt";
 pub const NIX_IN_SCOPE: &str = "nixInScope";
 pub const NIX_LAMBDA_ARG_PFX: &str = "nix__";
 pub const NIX_LAMBDA_BOUND: &str = "nixBound";
+// default of `TranslateOptions::name_prefix`
+pub const NIX_NAME_PREFIX: &str = "nix";
+
+// builtins which depend on the environment (files, network, time, ...)
+pub const IMPURE_BUILTINS: &[&str] = &[
+    "currentSystem",
+    "currentTime",
+    "fetchGit",
+    "fetchMercurial",
+    "fetchTarball",
+    "fetchurl",
+    "filterSource",
+    "findFile",
+    "getEnv",
+    "hashFile",
+    "import",
+    "nixPath",
+    "path",
+    "pathExists",
+    "readDir",
+    "readFile",
+    "scopedImport",
+    "storePath",
+    "toFile",
+];
+
+// builtins which can be evaluated at translation time,
+// keep in sync with `Context::fold_builtin` and `Context::fold_from_toml`
+// (checked by the `folded_builtins` test)
+pub const FOLDED_BUILTINS: &[&str] =
+    &["fromTOML", "langVersion", "nixVersion", "storeDir", "trace"];
 
 use IdentCateg::*;
 pub const DFL_VARS: &[(&str, IdentCateg)] = &[
@@ -34,6 +72,8 @@ pub const DFL_VARS: &[(&str, IdentCateg)] = &[
     ("__add", AlBuiltin("__add")),
     ("__all", AlBuiltin("__all")),
     ("__any", AlBuiltin("__any")),
+    // string context is modelled by the runtime (`nixBlti.NixStringWithContext`),
+    // it survives `+`, `toString` and `concatStringsSep`, but not interpolation
     ("__appendContext", AlBuiltin("__appendContext")),
     ("__attrNames", AlBuiltin("__attrNames")),
     ("__attrValues", AlBuiltin("__attrValues")),
@@ -43,9 +83,15 @@ pub const DFL_VARS: &[(&str, IdentCateg)] = &[
     ("__bitXor", AlBuiltin("__bitXor")),
     ("builtins", Literal(NIX_BUILTINS_RT)),
     ("__catAttrs", AlBuiltin("__catAttrs")),
+    // compares the components produced by `__splitVersion` pairwise (numerically if both
+    // are numbers, `pre` sorts first), missing components count as ""
     ("__compareVersions", AlBuiltin("__compareVersions")),
+    // flattens exactly one level, the elements of the inner lists aren't forced
     ("__concatLists", AlBuiltin("__concatLists")),
+    // forces the list and applies `f` to every element (without forcing it),
+    // then forces the resulting lists, but not their elements
     ("__concatMap", AlBuiltin("__concatMap")),
+    // the separator must be a string, even if it isn't used (less than two elements)
     ("__concatStringsSep", AlBuiltin("__concatStringsSep")),
     ("__currentSystem", AlBuiltin("__currentSystem")),
     ("__currentTime", AlBuiltin("__currentTime")),
@@ -54,6 +100,8 @@ pub const DFL_VARS: &[(&str, IdentCateg)] = &[
     ("derivationStrict", AlBuiltin("derivationStrict")),
     ("dirOf", AlBuiltin("dirOf")),
     ("__div", AlBuiltin("__div")),
+    // the index must be an integer within bounds (checked by the runtime,
+    // plain JS indexing would return `undefined`)
     ("__elemAt", AlBuiltin("__elemAt")),
     ("__elem", AlBuiltin("__elem")),
     ("false", Literal("false")),
@@ -67,18 +115,27 @@ pub const DFL_VARS: &[(&str, IdentCateg)] = &[
     ("__foldl'", AlBuiltin("__foldl'")),
     ("__fromJSON", AlBuiltin("__fromJSON")),
     ("fromTOML", AlBuiltin("fromTOML")),
+    // returns the formals of pattern lambdas (recorded via `NIX_SET_FUNCTION_ARGS`),
+    // `{}` for any other function, including partially applied ones
     ("__functionArgs", AlBuiltin("__functionArgs")),
+    // dedups via Nix equality on the `key` attribute of the items
     ("__genericClosure", AlBuiltin("__genericClosure")),
     ("__genList", AlBuiltin("__genList")),
+    // the name is forced to a string and looked up as own property (no prototype
+    // lookups), a missing attribute throws "attribute 'NAME' missing"
     ("__getAttr", AlBuiltin("__getAttr")),
     ("__getContext", AlBuiltin("__getContext")),
     ("__getEnv", AlBuiltin("__getEnv")),
+    // like `__getAttr`, but returns whether the attribute exists
     ("__hasAttr", AlBuiltin("__hasAttr")),
     ("__hasContext", AlBuiltin("__hasContext")),
     ("__hashFile", AlBuiltin("__hashFile")),
     ("__hashString", AlBuiltin("__hashString")),
+    // throws on an empty list (instead of returning `undefined`)
     ("__head", AlBuiltin("__head")),
     ("import", AlBuiltin("import")),
+    // forces both sets, iterates over the keys of the first one (like Nix, so it
+    // should be the smaller one), the (unforced) values are taken from the second one
     ("__intersectAttrs", AlBuiltin("__intersectAttrs")),
     ("__isAttrs", AlBuiltin("__isAttrs")),
     ("__isBool", AlBuiltin("__isBool")),
@@ -89,21 +146,29 @@ pub const DFL_VARS: &[(&str, IdentCateg)] = &[
     ("isNull", AlBuiltin("isNull")),
     ("__isPath", AlBuiltin("__isPath")),
     ("__isString", AlBuiltin("__isString")),
+    // runtime-provided, or folded into a literal via `TranslateOptions::lang_version`
     ("__langVersion", AlBuiltin("__langVersion")),
+    // only forces the list itself, never its elements
     ("__length", AlBuiltin("__length")),
     ("__lessThan", AlBuiltin("__lessThan")),
     ("__listToAttrs", AlBuiltin("__listToAttrs")),
     ("__mapAttrs", AlBuiltin("__mapAttrs")),
     ("map", AlBuiltin("map")),
+    // the POSIX extended regex gets translated into a JS regex by the runtime,
+    // including bracket expressions and character classes like `[[:alpha:]]`;
+    // alternatives are leftmost-first (JS) instead of leftmost-longest (POSIX)
     ("__match", AlBuiltin("__match")),
     ("__mul", AlBuiltin("__mul")),
     ("__nixPath", AlBuiltin("__nixPath")),
+    // runtime-provided, or folded into a literal via `TranslateOptions::nix_version`
     ("__nixVersion", AlBuiltin("__nixVersion")),
     ("null", Literal("null")),
     ("__parseDrvName", AlBuiltin("__parseDrvName")),
     ("__partition", AlBuiltin("__partition")),
     ("__pathExists", AlBuiltin("__pathExists")),
     ("__path", AlBuiltin("__path")),
+    // `"/" + nixBase32(sha256("nix-output:" + output))`,
+    // computed via the runtime-provided `hashString`
     ("placeholder", AlBuiltin("placeholder")),
     ("__readDir", AlBuiltin("__readDir")),
     ("__readFile", AlBuiltin("__readFile")),
@@ -112,24 +177,43 @@ pub const DFL_VARS: &[(&str, IdentCateg)] = &[
     ("scopedImport", AlBuiltin("scopedImport")),
     ("__seq", AlBuiltin("__seq")),
     ("__sort", AlBuiltin("__sort")),
+    // uses the same regex translation as `__match`
     ("__split", AlBuiltin("__split")),
+    // `.` and `-` separate components (consecutive separators are skipped), and
+    // digit/non-digit boundaries split too, e.g. "1..2pre_3" -> [ "1" "2" "pre_" "3" ]
     ("__splitVersion", AlBuiltin("__splitVersion")),
+    // defaults to "/nix/store", can be overridden by the runtime,
+    // or folded into a literal via `TranslateOptions::store_dir`
     ("__storeDir", AlBuiltin("__storeDir")),
+    // checks that the path is located directly inside of `storeDir`,
+    // and returns it unchanged (the runtime may provide additional checks)
     ("__storePath", AlBuiltin("__storePath")),
     ("__stringLength", AlBuiltin("__stringLength")),
     ("__sub", AlBuiltin("__sub")),
     ("__substring", AlBuiltin("__substring")),
+    // throws on an empty list, like `__head`
     ("__tail", AlBuiltin("__tail")),
     ("throw", AlBuiltin("throw")),
     ("__toFile", AlBuiltin("__toFile")),
+    // forces the value completely; the runtime must sort the attrset keys (like Nix does,
+    // `JSON.stringify` would keep the insertion order), so the output is reproducible
     ("__toJSON", AlBuiltin("__toJSON")),
     ("__toPath", AlBuiltin("__toPath")),
+    // the coercion rules (bools, null, lists, floats via `%f`) are implemented in the runtime
     ("toString", AlBuiltin("toString")),
     ("__toXML", AlBuiltin("__toXML")),
+    // the message is passed to `nixRt.trace` by the runtime,
+    // or it is dropped via `TranslateOptions::trace_mode`
     ("__trace", AlBuiltin("__trace")),
     ("true", Literal("true")),
     ("__tryEval", AlBuiltin("__tryEval")),
+    // forces its argument, the runtime maps its representations to the type strings:
+    //   int    <- `bigint`, or an integral number (int literals are emitted as numbers)
+    //   float  <- any other number
+    //   bool / string / null / list (array) / lambda (function) / set (other objects)
+    //   path   <- `nixBlti.NixPath`, if returned by `nixRt.export`
     ("__typeOf", AlBuiltin("__typeOf")),
+    // `allOutputs` context entries become plain `path` entries
     (
         "__unsafeDiscardOutputDependency",
         AlBuiltin("__unsafeDiscardOutputDependency"),
@@ -0,0 +1,47 @@
+use boa_engine::{Context, Source};
+
+// minimal runtime, provides `nixBlti`, `nixEvalStart` and `nixEvalOutput`
+const PRELUDE: &str = include_str!("eval_prelude.js");
+
+/// Translates and evaluates a pure Nix expression (no paths, imports or other I/O)
+/// using an embedded JS engine, and returns the deeply forced result as JSON.
+pub fn eval_pure(s: &str) -> Result<serde_json::Value, Vec<String>> {
+    eval_pure_with_options(s, &Default::default())
+}
+
+/// like [`eval_pure`], but translates using the given options; those which change
+/// the shape of the output (`omit_preamble`, `entry_export`, ...) are ignored
+pub fn eval_pure_with_options(
+    s: &str,
+    opts: &crate::TranslateOptions,
+) -> Result<serde_json::Value, Vec<String>> {
+    let opts = crate::TranslateOptions {
+        no_source_map: true,
+        omit_preamble: false,
+        entry_export: None,
+        web_worker: false,
+        ..opts.clone()
+    };
+    let js = crate::translate_with_options(s, "<eval>", &opts)?.js;
+    let code = format!(
+        "{}\nnixEvalStart(function(nixRt,nixBlti){{{}\n}});",
+        PRELUDE, js
+    );
+
+    let mut ctx = Context::default();
+    ctx.eval(Source::from_bytes(&code))
+        .map_err(|e| vec![e.to_string()])?;
+    ctx.run_jobs();
+
+    let out = ctx
+        .eval(Source::from_bytes("nixEvalOutput"))
+        .map_err(|e| vec![e.to_string()])?
+        .as_string()
+        .map(|i| i.to_std_string_escaped())
+        .ok_or_else(|| vec!["evaluation did not finish".to_string()])?;
+
+    match out.split_at(1) {
+        ("o", json) => serde_json::from_str(json).map_err(|e| vec![e.to_string()]),
+        (_, msg) => Err(vec![msg.to_string()]),
+    }
+}
@@ -0,0 +1,879 @@
+// minimal, pure subset of the `nix-builtins` package,
+// used by `eval_pure` (see `eval.rs`); I/O is not supported.
+// SPDX-License-Identifier: LGPL-2.1-or-later
+
+const nixBlti = (() => {
+  class NixAbortError extends Error {}
+  class NixEvalError extends Error {}
+  class ScopeError extends Error {}
+
+  // like `p-lazy`: the function only gets called once the value is awaited
+  class PLazy {
+    constructor(f) {
+      this.f = f;
+      this.p = null;
+    }
+    static from(f) {
+      return new PLazy(f);
+    }
+    then(res, rej) {
+      if (this.p === null) {
+        this.p = Promise.resolve().then(this.f);
+      }
+      return this.p.then(res, rej);
+    }
+    catch(rej) {
+      return this.then(undefined, rej);
+    }
+  }
+
+  // strings with a non-empty context (see `NixStringWithContext` in nix-builtins),
+  // the context is propagated by `+` only
+  class NixStringWithContext extends String {
+    constructor(s, context) {
+      super(s);
+      this.context = context;
+    }
+  }
+  const getContext = (s) => (s instanceof NixStringWithContext ? s.context : {});
+  const mergeContexts = (...ctxs) => {
+    let ret = Object.create(null);
+    for (const ctx of ctxs) {
+      for (const [path, ent] of Object.entries(ctx)) {
+        let dst = ret[path] || (ret[path] = {});
+        if (ent.path) dst.path = true;
+        if (ent.allOutputs) dst.allOutputs = true;
+        if (ent.outputs) {
+          dst.outputs = (dst.outputs || []).concat(ent.outputs).filter(onlyUnique);
+          dst.outputs.sort();
+        }
+      }
+    }
+    return ret;
+  };
+  const mkString = (s, ctx) =>
+    Object.keys(ctx).length ? new NixStringWithContext(s, ctx) : s;
+
+  const allKeys = Symbol("__all__");
+  const extractScope = Symbol("__dict__");
+  const lambdaArgs = Symbol("__functionArgs__");
+
+  const fixObjectProto = (...objs) =>
+    Object.assign(Object.create(null), ...objs);
+  const onlyUnique = (value, index, self) => self.indexOf(value) === index;
+
+  function setFunctionArgs(args, f) {
+    Object.defineProperty(f, lambdaArgs, { value: fixObjectProto(args) });
+    return f;
+  }
+
+  function mkScope(orig) {
+    if (orig === undefined) {
+      orig = null;
+    }
+    let orig_keys = orig ? () => Object.keys(orig) : () => [];
+    let current = Object.create(orig);
+    Object.defineProperty(current, allKeys, {
+      get: () => Object.keys(current).concat(orig_keys()).filter(onlyUnique),
+    });
+    Object.defineProperty(current, extractScope, {
+      get: () => fixObjectProto(current),
+    });
+    return new Proxy(current, {
+      set: function (target, key, value) {
+        if (key == "__proto__") throw new ScopeError("Tried modifying prototype");
+        let ret = !Object.prototype.hasOwnProperty.call(target, key);
+        if (ret) {
+          Object.defineProperty(target, key, {
+            value,
+            configurable: false,
+            enumerable: true,
+            writable: false,
+          });
+        }
+        return ret;
+      },
+    });
+  }
+
+  function mkScopeWith(...objs) {
+    return new Proxy(Object.create(null), {
+      get: (target, key) => {
+        if (key in target) return target[key];
+        let tmp = objs.find((obj) => key in obj);
+        return tmp !== undefined ? tmp[key] : undefined;
+      },
+      has: (target, key) => key in target || objs.some((obj) => key in obj),
+      set: (target, key) => {
+        throw new ScopeError(
+          "Tried overwriting key '" + String(key) + "' in read-only scope"
+        );
+      },
+    });
+  }
+
+  async function orDefault(selopf, dflf) {
+    let ret = undefined;
+    try {
+      ret = await selopf;
+    } catch (e) {
+      if (!(e instanceof TypeError)) throw e;
+    }
+    if (ret === undefined) {
+      ret = await dflf;
+    }
+    return ret;
+  }
+
+  // integers are represented as `bigint`, floats as `number`
+  const typeOf = (e) => {
+    if (e === null) return "null";
+    if (e instanceof Array) return "list";
+    if (e instanceof NixStringWithContext) return "string";
+    switch (typeof e) {
+      case "bigint":
+        return "int";
+      case "number":
+        return "float";
+      case "boolean":
+        return "bool";
+      case "function":
+        return "lambda";
+      case "object":
+        return "set";
+      default:
+        return typeof e;
+    }
+  };
+
+  const isNumeric = (x) => typeof x === "bigint" || typeof x === "number";
+
+  const tyforce = (fname, x, ty) => {
+    if (typeOf(x) !== ty && !(ty === "float" && typeOf(x) === "int")) {
+      throw new TypeError(
+        fname + ": value is " + typeOf(x) + " while a " + ty + " was expected"
+      );
+    }
+    return x;
+  };
+
+  // Nix equality, forces nested values
+  async function equal(a, b) {
+    a = await a;
+    b = await b;
+    if (a instanceof Array && b instanceof Array) {
+      if (a.length !== b.length) return false;
+      for (let i = 0; i < a.length; i++) {
+        if (!(await equal(a[i], b[i]))) return false;
+      }
+      return true;
+    }
+    if (typeOf(a) === "set" && typeOf(b) === "set") {
+      const ka = Object.keys(a).sort();
+      const kb = Object.keys(b).sort();
+      if (!(await equal(ka, kb))) return false;
+      for (const k of ka) {
+        if (!(await equal(a[k], b[k]))) return false;
+      }
+      return true;
+    }
+    // ints and floats are compared by value (`1 == 1.0`)
+    if (isNumeric(a) && isNumeric(b)) return a == b;
+    // the string context doesn't influence equality
+    if (typeOf(a) === "string" && typeOf(b) === "string") {
+      return a.valueOf() === b.valueOf();
+    }
+    return a === b;
+  }
+
+  // floats are formatted like C's `%f` (as Nix does)
+  function floatToString(x) {
+    if (Number.isNaN(x)) return "nan";
+    if (!Number.isFinite(x)) return x < 0 ? "-inf" : "inf";
+    const abs = Math.abs(x);
+    // `toFixed` switches to an exponent at 1e21, `%f` doesn't
+    const ret = abs < 1e21 ? abs.toFixed(6) : BigInt(abs).toString() + ".000000";
+    return (x < 0 || Object.is(x, -0) ? "-" : "") + ret;
+  }
+
+  async function toString(x) {
+    x = await x;
+    if (x === null) return "";
+    if (x instanceof NixStringWithContext) return x;
+    if (x instanceof Array) {
+      let parts = [];
+      for (const i of x) parts.push(await toString(i));
+      return parts.join(" ");
+    }
+    switch (typeof x) {
+      case "boolean":
+        return x ? "1" : "";
+      case "string":
+        return x;
+      case "number":
+        return floatToString(x);
+      case "bigint":
+        return x.toString();
+    }
+    if (typeOf(x) === "set") {
+      if ("__toString" in x) return await toString((await x.__toString)(x));
+      if ("outPath" in x) return await toString(x.outPath);
+    }
+    throw new NixEvalError("cannot coerce a " + typeOf(x) + " to a string");
+  }
+
+  const arith = (fname, f) => async (a, b) => {
+    a = await a;
+    b = await b;
+    // ints and floats can be mixed, the result is a float then
+    if (typeof a !== typeof b && isNumeric(a) && isNumeric(b)) {
+      a = Number(a);
+      b = Number(b);
+    }
+    if (typeof a !== typeof b) {
+      throw new TypeError(
+        fname + ": given types mismatch (" + typeOf(a) + " != " + typeOf(b) + ")"
+      );
+    }
+    return f(a, b);
+  };
+  const cmp = (fname, f) =>
+    arith(fname, (a, b) => {
+      if (!isNumeric(a) && typeof a !== "string") {
+        throw new TypeError(fname + ": cannot compare " + typeOf(a));
+      }
+      return f(a, b);
+    });
+  const bool = async (fname, x) => {
+    x = await x;
+    if (typeof x !== "boolean") {
+      throw new TypeError(fname + ": value is " + typeOf(x) + " while a bool was expected");
+    }
+    return x;
+  };
+
+  const add = arith("+", (a, b) => a + b);
+
+  const nixOp = {
+    u_Invert: async (a) => !(await bool("!", a)),
+    u_Negate: async (a) => -(await a),
+    _deepMerge: async function (attrs, value, ...path) {
+      attrs = await attrs;
+      while (path.length > 1) {
+        let pfi = path.shift();
+        if (!Object.prototype.hasOwnProperty.call(attrs, pfi)) {
+          attrs[pfi] = Object.create(null);
+        }
+        attrs = await attrs[pfi];
+      }
+      attrs[path[0]] = value;
+    },
+    _lambdaA2chk: function (attrs, key, fallback) {
+      let tmp = attrs[key];
+      if (tmp === undefined) {
+        if (fallback === undefined) {
+          throw new NixEvalError("Attrset element " + key + " missing at lambda call");
+        }
+        tmp = fallback;
+      }
+      return tmp;
+    },
+    Concat: arith("operator ++", (a, b) => {
+      if (!(a instanceof Array && b instanceof Array)) {
+        throw new TypeError("operator ++: both operands must be lists");
+      }
+      return a.concat(b);
+    }),
+    Update: arith("operator //", (a, b) => fixObjectProto({}, a, b)),
+    Add: async (a, b) => {
+      a = await a;
+      b = await b;
+      if (a instanceof NixStringWithContext || b instanceof NixStringWithContext) {
+        return mkString(
+          tyforce("+", a, "string") + tyforce("+", b, "string"),
+          mergeContexts(getContext(a), getContext(b))
+        );
+      }
+      return add(a, b);
+    },
+    Sub: arith("-", (a, b) => a - b),
+    Mul: arith("*", (a, b) => a * b),
+    // `bigint` division truncates, like integer division in Nix
+    Div: arith("/", (a, b) => {
+      if (!b) throw new RangeError("Division by zero");
+      return a / b;
+    }),
+    And: async (a, b) => (await bool("&&", a)) && (await bool("&&", b)),
+    Implication: async (a, b) => !(await bool("->", a)) || (await bool("->", b)),
+    Or: async (a, b) => (await bool("||", a)) || (await bool("||", b)),
+    Equal: equal,
+    NotEqual: async (a, b) => !(await equal(a, b)),
+    Less: cmp("<", (a, b) => a < b),
+    LessOrEq: cmp("<=", (a, b) => a <= b),
+    More: cmp(">", (a, b) => a > b),
+    MoreOrEq: cmp(">=", (a, b) => a >= b),
+  };
+
+  // see `posixToJsRegex` in nix-builtins
+  const posixClasses = {
+    alnum: "0-9A-Za-z",
+    alpha: "A-Za-z",
+    blank: " \\t",
+    cntrl: "\\x00-\\x1f\\x7f",
+    digit: "0-9",
+    graph: "\\x21-\\x7e",
+    lower: "a-z",
+    print: "\\x20-\\x7e",
+    punct: "!-\\/:-@\\[-`{-~",
+    space: " \\t\\n\\v\\f\\r",
+    upper: "A-Z",
+    xdigit: "0-9A-Fa-f",
+  };
+  function compileRegex(re, whole) {
+    let src = "";
+    let i = 0;
+    while (i < re.length) {
+      if (re[i] === "\\") {
+        src += re.slice(i, i + 2);
+        i += 2;
+      } else if (re[i] === "[") {
+        src += "[";
+        i++;
+        if (re[i] === "^") {
+          src += "^";
+          i++;
+        }
+        if (re[i] === "]") {
+          src += "\\]";
+          i++;
+        }
+        while (i < re.length && re[i] !== "]") {
+          const cls = /^\[:([a-z]+):\]/.exec(re.slice(i));
+          if (cls !== null) {
+            if (!Object.prototype.hasOwnProperty.call(posixClasses, cls[1])) {
+              throw new NixEvalError("invalid character class '" + cls[0] + "' in regex '" + re + "'");
+            }
+            src += posixClasses[cls[1]];
+            i += cls[0].length;
+          } else {
+            src += re[i] === "\\" || re[i] === "[" ? "\\" + re[i] : re[i];
+            i++;
+          }
+        }
+        if (i >= re.length) {
+          throw new NixEvalError("unterminated bracket expression in regex '" + re + "'");
+        }
+        src += "]";
+        i++;
+      } else {
+        src += re[i];
+        i++;
+      }
+    }
+    return whole ? new RegExp("^(?:" + src + ")$") : new RegExp(src, "g");
+  }
+  const regexGroups = (m) => m.slice(1).map((x) => (x === undefined ? null : x));
+
+  // see `splitVersion` and `versionComponentLess` in nix-builtins
+  const splitVersion = (s) => s.match(/[0-9]+|[^0-9.-]+/g) || [];
+  function versionComponentLess(a, b) {
+    const ina = /^[0-9]+$/.test(a);
+    const inb = /^[0-9]+$/.test(b);
+    if (ina && inb) return parseInt(a) < parseInt(b);
+    if (a === "" && inb) return true;
+    if (a === "pre" && b !== "pre") return true;
+    if (b === "pre") return false;
+    if (inb) return true;
+    if (ina) return false;
+    return a < b;
+  }
+
+  // see `nixFromJSON` in nix-builtins
+  function parseJSON(s) {
+    let pos = 0;
+    const fail = () => {
+      throw new NixEvalError("fromJSON: syntax error at offset " + pos);
+    };
+    const token = (re) => {
+      re.lastIndex = pos;
+      const m = re.exec(s);
+      if (m === null) fail();
+      pos += m[0].length;
+      return m[0];
+    };
+    const skipWs = () => token(/[ \t\n\r]*/y);
+    const str = () =>
+      JSON.parse(token(/"(?:[^"\\\u0000-\u001f]|\\(?:["\\/bfnrt]|u[0-9a-fA-F]{4}))*"/y));
+    const value = () => {
+      skipWs();
+      const open = s[pos];
+      if (open === "{" || open === "[") {
+        pos++;
+        const close = open === "{" ? "}" : "]";
+        let ret = open === "{" ? Object.create(null) : [];
+        skipWs();
+        if (s[pos] === close) {
+          pos++;
+          return ret;
+        }
+        while (true) {
+          skipWs();
+          if (open === "{") {
+            const key = str();
+            skipWs();
+            if (s[pos++] !== ":") fail();
+            ret[key] = value();
+          } else {
+            ret.push(value());
+          }
+          skipWs();
+          const sep = s[pos++];
+          if (sep === close) return ret;
+          if (sep !== ",") fail();
+        }
+      }
+      if (open === '"') return str();
+      for (const [lit, v] of [["true", true], ["false", false], ["null", null]]) {
+        if (s.startsWith(lit, pos)) {
+          pos += lit.length;
+          return v;
+        }
+      }
+      const num = token(/-?(?:0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?/y);
+      return /^-?[0-9]+$/.test(num) ? BigInt(num) : Number(num);
+    };
+    const ret = value();
+    skipWs();
+    if (pos < s.length) fail();
+    return ret;
+  }
+
+  const list = async (fname, x) => tyforce(fname, await x, "list");
+  const attrs = async (fname, x) => tyforce(fname, await x, "set");
+
+  const pureBltis = {
+    abort: async (s) => {
+      throw new NixAbortError(
+        "evaluation aborted with the following error message: '" + (await toString(s)) + "'"
+      );
+    },
+    add: (a) => (b) => nixOp.Add(a, b),
+    assert: (condstr) => async (cond) => {
+      if (!(await bool("assert", cond))) {
+        throw new NixEvalError("assertion failed: " + condstr);
+      }
+    },
+    all: (pred) => async (xs) => {
+      for (const i of await list("all", xs)) {
+        if (!(await (await pred)(i))) return false;
+      }
+      return true;
+    },
+    any: (pred) => async (xs) => {
+      for (const i of await list("any", xs)) {
+        if (await (await pred)(i)) return true;
+      }
+      return false;
+    },
+    appendContext: (s) => async (ctx) => {
+      s = tyforce("appendContext", await s, "string");
+      ctx = await attrs("appendContext", ctx);
+      let add = Object.create(null);
+      for (const path of Object.keys(ctx).sort()) {
+        const ent = await ctx[path];
+        let dst = {};
+        if (await ent.path) dst.path = true;
+        if (await ent.allOutputs) dst.allOutputs = true;
+        if (ent.outputs !== undefined) {
+          dst.outputs = [];
+          for (const i of await list("appendContext", ent.outputs)) {
+            dst.outputs.push(tyforce("appendContext", await i, "string").valueOf());
+          }
+        }
+        add[path] = dst;
+      }
+      return mkString(s.valueOf(), mergeContexts(getContext(s), add));
+    },
+    attrNames: async (aset) => Object.keys(await attrs("attrNames", aset)).sort(),
+    attrValues: async (aset) => {
+      aset = await attrs("attrValues", aset);
+      return Object.keys(aset)
+        .sort()
+        .map((k) => aset[k]);
+    },
+    compareVersions: (a) => async (b) => {
+      a = splitVersion(tyforce("compareVersions", await a, "string").valueOf());
+      b = splitVersion(tyforce("compareVersions", await b, "string").valueOf());
+      for (let i = 0; i < Math.max(a.length, b.length); i++) {
+        const x = i < a.length ? a[i] : "";
+        const y = i < b.length ? b[i] : "";
+        if (versionComponentLess(x, y)) return -1n;
+        if (versionComponentLess(y, x)) return 1n;
+      }
+      return 0n;
+    },
+    concatLists: async (xs) => {
+      let ret = [];
+      for (const i of await list("concatLists", xs)) {
+        ret = ret.concat(await list("concatLists", i));
+      }
+      return ret;
+    },
+    concatMap: (f) => async (xs) => {
+      let ret = [];
+      for (const i of await list("concatMap", xs)) {
+        ret = ret.concat(await list("concatMap", (await f)(i)));
+      }
+      return ret;
+    },
+    concatStringsSep: (sep) => async (xs) => {
+      sep = tyforce("concatStringsSep", await sep, "string");
+      let parts = [];
+      for (const i of await list("concatStringsSep", xs)) {
+        parts.push(tyforce("concatStringsSep", await i, "string"));
+      }
+      return parts.join(sep);
+    },
+    deepSeq: (a) => async (b) => {
+      await deepSeq(a, new Set());
+      return await b;
+    },
+    div: (a) => (b) => nixOp.Div(a, b),
+    elem: (x) => async (xs) => {
+      for (const i of await list("elem", xs)) {
+        if (await equal(x, i)) return true;
+      }
+      return false;
+    },
+    elemAt: (xs) => async (n) => {
+      xs = await list("elemAt", xs);
+      n = tyforce("elemAt", await n, "int");
+      if (n < 0 || n >= xs.length) throw new NixEvalError("list index " + n + " is out of bounds");
+      return await xs[Number(n)];
+    },
+    filter: (f) => async (xs) => {
+      let ret = [];
+      for (const i of await list("filter", xs)) {
+        if (await (await f)(i)) ret.push(i);
+      }
+      return ret;
+    },
+    "foldl'": (op) => (nul) => async (xs) => {
+      let acc = await nul;
+      for (const i of await list("foldl'", xs)) {
+        acc = await (await (await op)(acc))(i);
+      }
+      return acc;
+    },
+    fromJSON: async (s) => parseJSON(tyforce("fromJSON", await s, "string").valueOf()),
+    functionArgs: async (f) => fixObjectProto(tyforce("functionArgs", await f, "lambda")[lambdaArgs]),
+    genList: (gen) => async (len) =>
+      Array.from({ length: Number(tyforce("genList", await len, "int")) }, (dummy, i) =>
+        PLazy.from(async () => (await gen)(BigInt(i)))
+      ),
+    genericClosure: async (args) => {
+      args = await attrs("genericClosure", args);
+      const operator = await args.operator;
+      let work = [...(await list("genericClosure", args.startSet))];
+      let keys = [];
+      let ret = [];
+      while (work.length) {
+        const item = await work.shift();
+        const key = await item.key;
+        if (key === undefined) {
+          throw new NixEvalError("builtins.genericClosure: attribute 'key' required");
+        }
+        let seen = false;
+        for (const i of keys) {
+          if (await equal(i, key)) {
+            seen = true;
+            break;
+          }
+        }
+        if (seen) continue;
+        keys.push(key);
+        ret.push(item);
+        work.push(...(await list("genericClosure", operator(item))));
+      }
+      return ret;
+    },
+    getAttr: (k) => async (aset) => {
+      k = tyforce("getAttr", await k, "string");
+      aset = await attrs("getAttr", aset);
+      if (!Object.prototype.hasOwnProperty.call(aset, k)) {
+        throw new NixEvalError("attribute '" + k + "' missing");
+      }
+      return await aset[k];
+    },
+    hasAttr: (k) => async (aset) => {
+      k = tyforce("hasAttr", await k, "string");
+      return Object.prototype.hasOwnProperty.call(await attrs("hasAttr", aset), k);
+    },
+    hasContext: async (s) =>
+      Object.keys(getContext(tyforce("hasContext", await s, "string"))).length !== 0,
+    head: async (xs) => {
+      xs = await list("head", xs);
+      if (!xs.length) throw new NixEvalError("builtins.head called on empty list");
+      return await xs[0];
+    },
+    intersectAttrs: (a) => async (b) => {
+      a = await attrs("intersectAttrs", a);
+      b = await attrs("intersectAttrs", b);
+      let ret = Object.create(null);
+      for (const k of Object.keys(a)) {
+        if (Object.prototype.hasOwnProperty.call(b, k)) ret[k] = b[k];
+      }
+      return ret;
+    },
+    isAttrs: async (e) => typeOf(await e) === "set",
+    isBool: async (e) => typeOf(await e) === "bool",
+    isFloat: async (e) => typeOf(await e) === "float",
+    isFunction: async (e) => typeOf(await e) === "lambda",
+    isInt: async (e) => typeOf(await e) === "int",
+    isList: async (e) => typeOf(await e) === "list",
+    isNull: async (e) => (await e) === null,
+    isString: async (e) => typeOf(await e) === "string",
+    length: async (xs) => BigInt((await list("length", xs)).length),
+    lessThan: (a) => (b) => nixOp.Less(a, b),
+    listToAttrs: async (xs) => {
+      let ret = Object.create(null);
+      for (const i of await list("listToAttrs", xs)) {
+        const name = await (await i).name;
+        if (!Object.prototype.hasOwnProperty.call(ret, name)) {
+          ret[name] = (await i).value;
+        }
+      }
+      return ret;
+    },
+    map: (f) => async (xs) =>
+      (await list("map", xs)).map((x) => PLazy.from(async () => (await f)(x))),
+    mapAttrs: (f) => async (aset) => {
+      aset = await attrs("mapAttrs", aset);
+      let ret = Object.create(null);
+      for (const k of Object.keys(aset)) {
+        ret[k] = PLazy.from(async () => await (await (await f)(k))(aset[k]));
+      }
+      return ret;
+    },
+    match: (re) => async (s) => {
+      re = tyforce("match", await re, "string").valueOf();
+      const m = compileRegex(re, true).exec(tyforce("match", await s, "string"));
+      return m === null ? null : regexGroups(m);
+    },
+    mul: (a) => (b) => nixOp.Mul(a, b),
+    removeAttrs: (aset) => async (names) => {
+      let ret = fixObjectProto(await attrs("removeAttrs", aset));
+      for (const i of await list("removeAttrs", names)) delete ret[await i];
+      return ret;
+    },
+    seq: (a) => async (b) => {
+      await a;
+      return await b;
+    },
+    split: (re) => async (s) => {
+      const rx = compileRegex(tyforce("split", await re, "string").valueOf(), false);
+      const str = tyforce("split", await s, "string").valueOf();
+      let ret = [];
+      let last = 0;
+      let m;
+      while ((m = rx.exec(str)) !== null) {
+        ret.push(str.slice(last, m.index), regexGroups(m));
+        last = m.index + m[0].length;
+        // avoid getting stuck at empty matches
+        if (m[0] === "") rx.lastIndex++;
+      }
+      ret.push(str.slice(last));
+      return ret;
+    },
+    splitVersion: async (s) =>
+      splitVersion(tyforce("splitVersion", await s, "string").valueOf()),
+    stringLength: async (s) => BigInt(tyforce("stringLength", await s, "string").length),
+    sub: (a) => (b) => nixOp.Sub(a, b),
+    substring: (start) => (len) => async (s) => {
+      start = Number(tyforce("substring", await start, "int"));
+      len = Number(tyforce("substring", await len, "int"));
+      s = tyforce("substring", await s, "string");
+      return len < 0 ? s.slice(start) : s.substr(start, len);
+    },
+    tail: async (xs) => {
+      xs = await list("tail", xs);
+      if (!xs.length) throw new NixEvalError("builtins.tail called on empty list");
+      return xs.slice(1);
+    },
+    throw: async (s) => {
+      throw new NixEvalError(await toString(s));
+    },
+    toString,
+    toJSON: async (e) => stringifyJSON(await deepForce(e)),
+    trace: (e1) => (e2) => e2,
+    tryEval: async (e) => {
+      try {
+        return fixObjectProto({ value: await e, success: true });
+      } catch (err) {
+        if (!(err instanceof NixEvalError)) throw err;
+        return fixObjectProto({ value: false, success: false });
+      }
+    },
+    typeOf: async (e) => typeOf(await e),
+    unsafeDiscardStringContext: async (s) =>
+      tyforce("unsafeDiscardStringContext", await s, "string").valueOf(),
+  };
+
+  const unsupported = (name) => () => {
+    throw new NixEvalError(name + " is not supported in pure evaluation");
+  };
+
+  // builtins which depend on the environment (see `IMPURE_BUILTINS` in `consts.rs`)
+  // or on the runtime fail once they are accessed
+  const unsupportedBltis = [
+    "currentSystem",
+    "currentTime",
+    "fetchGit",
+    "fetchMercurial",
+    "fetchTarball",
+    "fetchurl",
+    "filterSource",
+    "findFile",
+    "getEnv",
+    "hashFile",
+    "import",
+    "nixPath",
+    "nixVersion",
+    "path",
+    "pathExists",
+    "readDir",
+    "readFile",
+    "scopedImport",
+    "storePath",
+    "toFile",
+  ];
+
+  function initRtDep(nixRt) {
+    let ret = Object.create(pureBltis);
+    for (const i of unsupportedBltis) {
+      Object.defineProperty(ret, i, { get: unsupported(i) });
+    }
+    return ret;
+  }
+
+  // forces a value completely, skipping already visited values
+  async function deepSeq(x, seen) {
+    x = await x;
+    if (typeof x !== "object" || x === null || seen.has(x)) return;
+    seen.add(x);
+    for (const i of x instanceof Array ? x : Object.values(x)) {
+      await deepSeq(i, seen);
+    }
+  }
+
+  // forces a value completely, converting it into something JSON-serializable
+  async function deepForce(x) {
+    x = await x;
+    if (x === undefined) return null;
+    if (x instanceof NixStringWithContext) return x.valueOf();
+    if (x instanceof Array) {
+      let ret = [];
+      for (const i of x) ret.push(await deepForce(i));
+      return ret;
+    }
+    switch (typeOf(x)) {
+      case "set": {
+        let ret = {};
+        for (const k of Object.keys(x).sort()) ret[k] = await deepForce(x[k]);
+        return ret;
+      }
+      case "lambda":
+        throw new NixEvalError("cannot convert a function to JSON");
+    }
+    return x;
+  }
+
+  // formats finite floats like Nix' `toJSON` does (see `floatToJSON` in nix-builtins)
+  function floatToJSON(x) {
+    if (x === 0) return Object.is(x, -0) ? "-0.0" : "0.0";
+    const [mant, exp] = Math.abs(x).toExponential().split("e");
+    const digits = mant.replace(".", "");
+    const k = digits.length;
+    const n = Number(exp) + 1;
+    let ret;
+    if (k <= n && n <= 15) {
+      ret = digits + "0".repeat(n - k) + ".0";
+    } else if (0 < n && n <= 15) {
+      ret = digits.slice(0, n) + "." + digits.slice(n);
+    } else if (-4 < n && n <= 0) {
+      ret = "0." + "0".repeat(-n) + digits;
+    } else {
+      const e = n - 1;
+      ret =
+        digits[0] +
+        (k > 1 ? "." + digits.slice(1) : "") +
+        (e < 0 ? "e-" : "e+") +
+        String(Math.abs(e)).padStart(2, "0");
+    }
+    return (x < 0 ? "-" : "") + ret;
+  }
+
+  // like `JSON.stringify`, for values forced via `deepForce`
+  function stringifyJSON(x) {
+    if (x instanceof Array) return "[" + x.map(stringifyJSON).join(",") + "]";
+    switch (typeof x) {
+      case "bigint":
+        return x.toString();
+      case "number":
+        if (!Number.isFinite(x)) {
+          throw new NixEvalError("toJSON: cannot convert " + x + " to JSON");
+        }
+        return floatToJSON(x);
+      case "object":
+        if (x === null) break;
+        return (
+          "{" +
+          Object.keys(x)
+            .map((k) => JSON.stringify(k) + ":" + stringifyJSON(x[k]))
+            .join(",") +
+          "}"
+        );
+    }
+    return JSON.stringify(x);
+  }
+
+  return {
+    NixAbortError,
+    NixEvalError,
+    PLazy,
+    allKeys,
+    extractScope,
+    lambdaArgs,
+    deepForce,
+    initRtDep,
+    stringifyJSON,
+    unsupported,
+    mkScope,
+    mkScopeWith,
+    nixOp,
+    orDefault,
+    setFunctionArgs,
+  };
+})();
+
+// the outcome of `nixEvalStart`, filled in once the evaluation finished:
+// `"o" + JSON` on success, `"e" + message` on failure.
+var nixEvalOutput = null;
+
+function nixEvalStart(f) {
+  const nixRt = {
+    export: nixBlti.unsupported("path"),
+    import: nixBlti.unsupported("import"),
+  };
+  Promise.resolve()
+    .then(() => nixBlti.deepForce(f(nixRt, nixBlti)))
+    .then(nixBlti.stringifyJSON)
+    .then(
+      (v) => {
+        nixEvalOutput = "o" + v;
+      },
+      (e) => {
+        nixEvalOutput = "e" + (e instanceof Error ? e.message : String(e));
+      }
+    );
+}
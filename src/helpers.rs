@@ -1,16 +1,141 @@
 use crate::{Context, TranslateResult};
 use rnix::SyntaxNode as NixNode;
 
+// maximum number of characters of source snippets, used for `assert` conditions
+// embedded into the output and in diagnostics
+const SNIPPET_MAX: usize = 64;
+
 pub fn attrelem_raw_safe(s: &str) -> bool {
     !s.is_empty()
         && s.chars().next().unwrap().is_ascii_alphabetic()
         && !s.contains(|i: char| !i.is_ascii_alphanumeric())
 }
 
+/// like `Str::parts`, but the indentation of indented strings is only stripped
+/// at the start of lines; the literal following an interpolation continues its line,
+/// e.g. the space in `${x} y` is kept
+pub fn str_parts(s: &rnix::types::Str) -> Vec<rnix::value::StrPart> {
+    use rnix::{
+        types::{StrInterpol, TypedNode},
+        value::{remove_indent, remove_trailing, unescape, StrPart},
+        NodeOrToken,
+        SyntaxKind::{NODE_STRING_INTERPOL, TOKEN_STRING_CONTENT},
+    };
+    let multiline = s.first_token().is_some_and(|t| t.text() == "''");
+    let indention = |line: &str| {
+        line.chars()
+            .take_while(|&c| c != '\n' && c.is_whitespace())
+            .count()
+    };
+    let mut parts = Vec::new();
+    let mut common = usize::MAX;
+    let mut last_was_ast = false;
+    for child in s.node().children_with_tokens() {
+        match &child {
+            NodeOrToken::Token(token) if token.kind() == TOKEN_STRING_CONTENT => {
+                let text = token.text();
+                let line_count = text.lines().count();
+                let next_is_ast = child
+                    .next_sibling_or_token()
+                    .is_some_and(|i| i.kind() == NODE_STRING_INTERPOL);
+                for (i, line) in text.lines().enumerate().skip(last_was_ast as usize) {
+                    let indent = indention(line);
+                    // empty lines don't count, unless an interpolation starts there
+                    if (i != line_count - 1 || !next_is_ast) && indent == line.chars().count() {
+                        continue;
+                    }
+                    common = common.min(indent);
+                }
+                parts.push(StrPart::Literal(text.to_string()));
+            }
+            NodeOrToken::Node(node) if node.kind() == NODE_STRING_INTERPOL => {
+                parts.push(StrPart::Ast(StrInterpol::cast(node.clone()).unwrap()));
+                last_was_ast = true;
+            }
+            _ => {}
+        }
+    }
+    let literals = parts
+        .iter()
+        .filter(|i| matches!(i, StrPart::Literal(_)))
+        .count();
+    let mut n = 0;
+    let mut after_ast = false;
+    for part in parts.iter_mut() {
+        let text = match part {
+            StrPart::Literal(text) => text,
+            StrPart::Ast(_) => {
+                after_ast = true;
+                continue;
+            }
+        };
+        if multiline {
+            *text = match text.find('\n') {
+                Some(pos) if after_ast => {
+                    text[..=pos].to_string() + &remove_indent(&text[pos + 1..], false, common)
+                }
+                None if after_ast => text.clone(),
+                _ => remove_indent(text, n == 0, common),
+            };
+            if n == literals - 1 {
+                remove_trailing(text);
+            }
+        }
+        *text = unescape(text, multiline);
+        n += 1;
+        after_ast = false;
+    }
+    parts
+}
+
+/// maps a Nix identifier to a JS identifier (prefixed with `NIX_LAMBDA_ARG_PFX`).
+/// Nix identifiers never contain `$`, which is thus used as escape character:
+/// `-` becomes `$_` and `'` becomes `$$`, which keeps the mapping injective,
+/// and the prefix avoids collisions with JS keywords and our own variables.
+pub fn mangle_ident(s: &str) -> String {
+    let mut ret = String::with_capacity(crate::consts::NIX_LAMBDA_ARG_PFX.len() + s.len());
+    ret += crate::consts::NIX_LAMBDA_ARG_PFX;
+    for i in s.chars() {
+        match i {
+            '-' => ret += "$_",
+            '\'' => ret += "$$",
+            _ => ret.push(i),
+        }
+    }
+    ret
+}
+
 pub fn escape_str(s: &str) -> String {
     serde_json::value::Value::String(s.to_string()).to_string()
 }
 
+/// converts a TOML value into an equivalent JS expression (attrsets are
+/// created like attrset literals), `None` if it has no Nix equivalent (datetimes)
+pub fn toml_to_js(v: &toml::Value) -> Option<String> {
+    use toml::Value as Tv;
+    Some(match v {
+        Tv::String(s) => escape_str(s),
+        Tv::Integer(i) => format!("{}n", i),
+        Tv::Float(f) => serde_json::Number::from_f64(*f)?.to_string(),
+        Tv::Boolean(b) => b.to_string(),
+        Tv::Datetime(_) => return None,
+        Tv::Array(xs) => format!(
+            "[{}]",
+            xs.iter()
+                .map(toml_to_js)
+                .collect::<Option<Vec<_>>>()?
+                .join(",")
+        ),
+        Tv::Table(t) => format!(
+            "Object.assign(Object.create(null),{{{}}})",
+            t.iter()
+                .map(|(k, v)| Some(format!("{}:{}", escape_str(k), toml_to_js(v)?)))
+                .collect::<Option<Vec<_>>>()?
+                .join(",")
+        ),
+    })
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum St {
     Did,
@@ -75,6 +200,43 @@ impl Context<'_> {
         *self.acc += x;
     }
 
+    /// pushes `x`, but without the whitespace in it if `TranslateOptions::minify` is set;
+    /// `x` must not contain whitespace which separates tokens
+    pub(crate) fn push_ws(&mut self, x: &str) {
+        if self.opts.minify {
+            *self.acc += &x.replace(' ', "");
+        } else {
+            *self.acc += x;
+        }
+    }
+
+    /// resolves the name of a generated identifier (e.g. `NIX_IN_SCOPE`), applying
+    /// `TranslateOptions::name_prefix` and shortening temporaries if `minify` is set;
+    /// single letters can't collide with the (always prefixed) translated identifiers
+    pub(crate) fn internal_name(&self, name: &str) -> String {
+        let short = match (self.opts.minify, name) {
+            (true, "nixInhR") => Some("r"),
+            (true, "nixAttrsScope") => Some("s"),
+            _ => None,
+        };
+        let pfx = self.opts.name_prefix.as_str();
+        match (short, name.strip_prefix("nix")) {
+            // without a custom prefix, the short names stay unprefixed
+            (Some(short), _) if pfx == crate::consts::NIX_NAME_PREFIX => short.to_string(),
+            (Some(short), _) => format!("{}{}", pfx, short),
+            (None, Some(rest)) => format!("{}{}", pfx, rest),
+            (None, None) => name.to_string(),
+        }
+    }
+
+    /// like `internal_name`, but with a numeric suffix which is unique within
+    /// the output, for temporaries which would otherwise shadow each other when nested
+    pub(crate) fn fresh_name(&mut self, name: &str) -> String {
+        let ret = format!("{}{}", self.internal_name(name), self.tmp_count);
+        self.tmp_count += 1;
+        ret
+    }
+
     pub(crate) fn lazyness_incoming<R>(
         &mut self,
         mut sctx: StackCtx,
@@ -96,14 +258,16 @@ impl Context<'_> {
         let lta = matches!(await_tr, Tr::Forward)
             || (matches!(await_tr, Tr::Flush) && matches!(adj, Ladj::Front));
         if do_lazy && ((!do_await && !matches!(adj, Ladj::Back)) || lta) {
-            self.push("nixBlti.PLazy.from(async ()=>");
+            // thunks may be awaited multiple times (e.g. shared `let` bindings),
+            // `PLazy` evaluates them at most once and caches the result
+            self.push_ws("nixBlti.PLazy.from(async ()=>");
+            self.stats.async_wraps += 1;
             finisher.push(")");
             sctx.await_st = St::Want;
             sctx.lazy_st = St::Nothing;
 
             if !lta {
-                self.push("(await ");
-                finisher.push(")");
+                // the return value of an async function gets flushed anyways
                 sctx.await_st = St::Did;
             }
         }
@@ -115,6 +279,9 @@ impl Context<'_> {
     }
 
     pub(crate) fn snapshot_pos(&mut self, inpos: rnix::TextSize) -> Option<()> {
+        if self.opts.no_source_map {
+            return None;
+        }
         let mut lp_dst = self.lp_dst;
         // use the line cache here because it can deal with backwards jumps
         let (src_line, src_col) = self.line_cache.run(usize::from(inpos));
@@ -136,6 +303,7 @@ impl Context<'_> {
         }
         use vlq::encode as vlqe;
         vlqe(dst_ocol.into(), &mut self.mappings).unwrap();
+        self.stats.map_segments += 1;
 
         if !(src_oline == 0 && src_ocol == 0) {
             vlqe(0, self.mappings).unwrap();
@@ -172,12 +340,40 @@ impl Context<'_> {
                     idx
                 }
             };
-            vlq::encode(idx.try_into().unwrap(), &mut self.mappings).unwrap();
+            // like the other fields, the name index is relative to the previous one
+            let rel = idx as i64 - self.lp_name as i64;
+            vlq::encode(rel, &mut self.mappings).unwrap();
+            self.lp_name = idx;
         }
 
         ret
     }
 
+    /// the source text of `txtrng`, abbreviated to `SNIPPET_MAX` characters
+    pub(crate) fn snippet(&self, txtrng: rnix::TextRange) -> String {
+        let mut ret = self.inp[txtrng].to_string();
+        if let Some((idx, _)) = ret.char_indices().nth(SNIPPET_MAX) {
+            ret.truncate(idx);
+            ret += " ...";
+        }
+        ret
+    }
+
+    /// the (trimmed) source line containing the start of `txtrng`, abbreviated like `snippet`
+    pub(crate) fn line_snippet(&self, txtrng: rnix::TextRange) -> String {
+        let pos: usize = txtrng.start().into();
+        let start = self.inp[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.inp[pos..]
+            .find('\n')
+            .map_or(self.inp.len(), |i| pos + i);
+        let line = self.inp[start..end].trim();
+        let start = start + self.inp[start..end].find(line).unwrap_or(0);
+        self.snippet(rnix::TextRange::new(
+            (start as u32).into(),
+            ((start + line.len()) as u32).into(),
+        ))
+    }
+
     pub(crate) fn txtrng_to_lineno(&self, txtrng: rnix::TextRange) -> usize {
         let bytepos: usize = txtrng.start().into();
         self.inp
@@ -196,13 +392,97 @@ impl Context<'_> {
     ) -> TranslateResult {
         match x {
             None => {
-                return Err(format!(
-                    "line {}: {} missing",
-                    self.txtrng_to_lineno(txtrng),
-                    desc
-                ));
+                let msg = format!("line {}: {} missing", self.txtrng_to_lineno(txtrng), desc);
+                if self.opts.recover_parse_errors {
+                    return self.translate_parse_error(sctx, &msg);
+                }
+                return Err(msg);
             }
             Some(x) => self.translate_node(sctx, x),
         }
     }
+
+    /// emits a call to `nixRt.parseError(msg)` as placeholder for a (partially)
+    /// unparsable expression, see `TranslateOptions::recover_parse_errors`
+    pub(crate) fn translate_parse_error(&mut self, sctx: StackCtx, msg: &str) -> TranslateResult {
+        self.lazyness_incoming(sctx, Tr::Need, Tr::Need, Ladj::Front, |this, _| {
+            this.push(crate::consts::NIX_RUNTIME);
+            this.push(".parseError(");
+            this.push(&escape_str(msg));
+            this.push(")");
+            TranslateResult::Ok(())
+        })
+    }
+}
+
+/// a decoded source map segment, with absolute positions
+pub struct MapSegment {
+    pub gen_line: usize,
+    pub gen_col: usize,
+    /// source index, line, column and the optional name index
+    pub src: Option<(usize, usize, usize, Option<usize>)>,
+}
+
+/// decodes the `mappings` of a source map (which are relative to the previous segment)
+pub fn decode_mappings(mappings: &str) -> Vec<MapSegment> {
+    let mut ret = Vec::new();
+    let mut state = [0i64; 4];
+    for (gen_line, line) in mappings.split(';').enumerate() {
+        let mut gen_col = 0i64;
+        for seg in line.split(',').filter(|i| !i.is_empty()) {
+            let mut it = seg.bytes();
+            let mut fields = Vec::with_capacity(5);
+            while let Ok(x) = vlq::decode(&mut it) {
+                fields.push(x);
+            }
+            gen_col += fields[0];
+            let src = if fields.len() >= 4 {
+                for (st, x) in state.iter_mut().zip(&fields[1..]) {
+                    *st += x;
+                }
+                Some((
+                    state[0] as usize,
+                    state[1] as usize,
+                    state[2] as usize,
+                    (fields.len() >= 5).then_some(state[3] as usize),
+                ))
+            } else {
+                None
+            };
+            ret.push(MapSegment {
+                gen_line,
+                gen_col: gen_col as usize,
+                src,
+            });
+        }
+    }
+    ret
+}
+
+/// inverse of `decode_mappings`, the segments must be ordered by their generated position
+pub fn encode_mappings(segments: &[MapSegment]) -> String {
+    let mut ret = Vec::new();
+    let (mut line, mut col) = (0, 0i64);
+    let mut state = [0i64; 4];
+    for (n, seg) in segments.iter().enumerate() {
+        if seg.gen_line > line {
+            ret.resize(ret.len() + seg.gen_line - line, b';');
+            line = seg.gen_line;
+            col = 0;
+        } else if n != 0 {
+            ret.push(b',');
+        }
+        vlq::encode(seg.gen_col as i64 - col, &mut ret).unwrap();
+        col = seg.gen_col as i64;
+        if let Some((src, sline, scol, name)) = seg.src {
+            let fields = [src, sline, scol].into_iter().map(Some).chain([name]);
+            for (st, x) in state.iter_mut().zip(fields) {
+                if let Some(x) = x {
+                    vlq::encode(x as i64 - *st, &mut ret).unwrap();
+                    *st = x as i64;
+                }
+            }
+        }
+    }
+    String::from_utf8(ret).unwrap()
 }
@@ -9,9 +9,16 @@
  - `export(anchor,path)`: export a path into the nix store
  - `import(to_be_imported_path)`: import a nix file,
     should callback into the parser.
+ - `readFile(path)`: read a file (given as resolved path string),
+    returns its contents as a string
+ - `parseError(msg)`: should throw, only used as placeholder for unparsable
+    expressions with `TranslateOptions::recover_parse_errors`
+ - `trace(msg)` (optional): receives the (forced) messages of `builtins.trace`,
+    defaults to `console.debug`; unused with `TraceMode::Suppress`
 
  It also expects a `nixBlti` object as the second argument, which should
  be the objects/namespace of all exported objects of the npm package `nix-builtins`.
+ Its `PLazy.from(f)` must memoize: `f` gets called at most once, on the first await.
 **/
 // SPDX-License-Identifier: LGPL-2.1-or-later
 use rnix::{types::*, SyntaxNode as NixNode};
@@ -20,18 +27,36 @@ mod consts;
 use consts::*;
 mod helpers;
 use helpers::*;
+#[cfg(feature = "eval")]
+mod eval;
+#[cfg(feature = "eval")]
+pub use eval::{eval_pure, eval_pure_with_options};
 
 struct Context<'a> {
     inp: &'a str,
+    opts: &'a TranslateOptions,
     acc: &'a mut String,
     vars: Vec<(String, IdentCateg)>,
-    with_stack: usize,
+    // indices into `vars` which got referenced at least once
+    used_vars: std::collections::HashSet<usize>,
+    // namespaces of the enclosing `with` statements,
+    // indices into `vars` if bound to a `LetLetAttrs` variable
+    with_stack: Vec<Option<usize>>,
+    // number of temporaries named via `fresh_name`
+    tmp_count: usize,
+    // set by `translate_let_vars` right before translating a `let` to be flattened
+    flatten_let: bool,
+    // if set, unknown identifiers get recorded here instead of being an error
+    free_vars: Option<&'a mut FreeVars>,
+    warnings: Vec<String>,
+    stats: TranslateStats,
     names: &'a mut Vec<String>,
     mappings: &'a mut Vec<u8>,
     // tracking positions for offset calc
     line_cache: linetrack::LineCache,
     lp_src: (usize, usize),
     lp_dst: linetrack::PosTrackerExtern,
+    lp_name: usize,
 }
 
 enum LetBody {
@@ -58,28 +83,131 @@ impl Context<'_> {
         ret
     }
 
+    fn lookup_var(&self, vn: &str) -> Option<usize> {
+        self.vars.iter().rposition(|(ref i, _)| vn == i)
+    }
+
     fn resolve_ident(&self, id: &Ident) -> Result<IdentCateg, String> {
         let vn = id.as_str();
-        let tmp = self
-            .vars
-            .iter()
-            .rev()
-            .find(|(ref i, _)| vn == i)
-            .map(|(_, c)| *c);
-        if let Some(ret) = tmp {
-            Ok(ret)
-        } else if self.with_stack > 0 {
+        if let Some(idx) = self.lookup_var(vn) {
+            Ok(self.vars[idx].1.clone())
+        } else if let Some(x) = self
+            .opts
+            .builtin_resolver
+            .as_ref()
+            .and_then(|r| r.resolve(vn))
+        {
+            Ok(match x {
+                ResolvedBuiltin::Builtin(name) => IdentCateg::AlBuiltin(name),
+                ResolvedBuiltin::Literal(lit) => IdentCateg::Literal(lit),
+            })
+        } else if !self.with_stack.is_empty() {
+            // the innermost `with` containing the name wins, which can only be
+            // determined statically as long as the namespaces are known attrsets
+            for ns in self.with_stack.iter().rev() {
+                match ns.map(|idx| (idx, &self.vars[idx])) {
+                    Some((idx, (name, IdentCateg::LetLetAttrs(keys))))
+                        if self.lookup_var(name) == Some(idx) =>
+                    {
+                        if keys.iter().any(|i| i == vn) {
+                            return Ok(IdentCateg::WithLetAttr(idx));
+                        }
+                    }
+                    _ => break,
+                }
+            }
             // no static analysis feasible
             Ok(IdentCateg::WithScopeVar)
         } else {
+            let txtrng = id.node().text_range();
             Err(format!(
-                "line {}: unknown identifier {}",
-                self.txtrng_to_lineno(id.node().text_range()),
-                vn
+                "line {}: unknown identifier {} (in `{}`)",
+                self.txtrng_to_lineno(txtrng),
+                vn,
+                self.line_snippet(txtrng)
             ))
         }
     }
 
+    /// checks if a node always evaluates to a boolean (or throws)
+    fn is_provably_bool(&self, node: &NixNode) -> bool {
+        use BinOpKind as Bok;
+        match ParsedType::try_from(node.clone()) {
+            Ok(ParsedType::Ident(id)) => matches!(
+                self.resolve_ident(&id),
+                Ok(IdentCateg::Literal("true" | "false"))
+            ),
+            Ok(ParsedType::Paren(p)) => matches!(p.inner(), Some(i) if self.is_provably_bool(&i)),
+            Ok(ParsedType::BinOp(bo)) => matches!(
+                bo.operator(),
+                Some(
+                    Bok::And
+                        | Bok::Equal
+                        | Bok::Implication
+                        | Bok::IsSet
+                        | Bok::Less
+                        | Bok::LessOrEq
+                        | Bok::More
+                        | Bok::MoreOrEq
+                        | Bok::NotEqual
+                        | Bok::Or
+                )
+            ),
+            _ => false,
+        }
+    }
+
+    /// returns a JS literal for builtins which are fixed via the options
+    fn fold_builtin(&self, name: &str) -> Option<String> {
+        match name.strip_prefix("__").unwrap_or(name) {
+            "storeDir" => self.opts.store_dir.as_deref().map(escape_str),
+            "nixVersion" => self.opts.nix_version.as_deref().map(escape_str),
+            "langVersion" => self.opts.lang_version.map(|v| format!("{}n", v)),
+            "trace" => match self.opts.trace_mode {
+                TraceMode::Runtime => None,
+                TraceMode::Suppress => Some("(_=>x=>x)".to_string()),
+            },
+            _ => None,
+        }
+    }
+
+    /// `builtins.fromTOML` applied to a string literal gets evaluated right away,
+    /// anything else (including invalid TOML) is left to the runtime
+    fn fold_from_toml(&self, app: &Apply) -> Option<String> {
+        let lambda = app.lambda()?;
+        let is_from_toml = if let Some(id) = Ident::cast(lambda.clone()) {
+            matches!(
+                self.resolve_ident(&id),
+                Ok(IdentCateg::AlBuiltin("fromTOML"))
+            )
+        } else if let Some(sel) = Select::cast(lambda) {
+            matches!(
+                (sel.set().and_then(Ident::cast), sel.index().and_then(Ident::cast)),
+                (Some(set), Some(key))
+                    if key.as_str() == "fromTOML"
+                        && matches!(
+                            self.resolve_ident(&set),
+                            Ok(IdentCateg::Literal(NIX_BUILTINS_RT))
+                        )
+            )
+        } else {
+            false
+        };
+        if !is_from_toml {
+            return None;
+        }
+        use rnix::value::StrPart as Sp;
+        // indented strings might consist of multiple literal parts
+        let lit = str_parts(&Str::cast(app.value()?)?)
+            .into_iter()
+            .map(|i| match i {
+                Sp::Literal(lit) => Some(lit),
+                Sp::Ast(_) => None,
+            })
+            .collect::<Option<String>>()?;
+        toml_to_js(&toml::from_str(&lit).ok()?)
+    }
+
     fn translate_node_ident_raw(
         &mut self,
         sctx: Option<StackCtx>,
@@ -104,26 +232,47 @@ impl Context<'_> {
         };
 
         match categ {
-            IdentCateg::Literal(lit) => self.snapshot_ident(txtrng, |this| this.push(lit)),
-            IdentCateg::AlBuiltin("builtins") => self.snapshot_ident(txtrng, |this| {
-                this.push(NIX_BUILTINS_RT);
+            IdentCateg::Literal(lit) => self.snapshot_ident(txtrng, |this| {
+                this.push(&this.internal_name(lit));
             }),
-            IdentCateg::AlBuiltin(ablti) => self.snapshot_ident(txtrng, |this| {
-                this.push(NIX_BUILTINS_RT);
-                this.push(".");
-                this.push(ablti.strip_prefix("__").unwrap_or(ablti));
+            IdentCateg::AlBuiltin("builtins") => self.snapshot_ident(txtrng, |this| {
+                this.push(&this.internal_name(NIX_BUILTINS_RT));
             }),
-            IdentCateg::LambdaArg | IdentCateg::LetLetVar => {
+            IdentCateg::AlBuiltin(ablti) => {
+                let folded = self.fold_builtin(ablti);
+                self.snapshot_ident(txtrng, |this| {
+                    if let Some(lit) = folded {
+                        this.push(&lit);
+                    } else {
+                        this.push(&this.internal_name(NIX_BUILTINS_RT));
+                        this.push(".");
+                        this.push(ablti.strip_prefix("__").unwrap_or(ablti));
+                    }
+                })
+            }
+            IdentCateg::LambdaArg | IdentCateg::LetLetVar | IdentCateg::LetLetAttrs(_) => {
                 handle_lazyness(self, &mut |this: &mut Self| {
                     this.snapshot_ident(txtrng, |this| {
-                        this.push(NIX_LAMBDA_ARG_PFX);
-                        this.push(&vn.replace("-", "_$_").replace("'", "_$"));
+                        this.push(&this.internal_name(&mangle_ident(vn)));
+                    })
+                })
+            }
+            IdentCateg::WithLetAttr(idx) => {
+                let ns = self.internal_name(&mangle_ident(&self.vars[idx].0));
+                handle_lazyness(self, &mut |this: &mut Self| {
+                    this.snapshot_ident(txtrng, |this| {
+                        this.push(&format!("(await {})", ns));
+                        this.push(&if attrelem_raw_safe(vn) {
+                            format!(".{}", vn)
+                        } else {
+                            format!("[{}]", escape_str(vn))
+                        });
                     })
                 })
             }
             _ => handle_lazyness(self, &mut |this: &mut Self| {
                 this.snapshot_ident(txtrng, |this| {
-                    this.push(NIX_IN_SCOPE);
+                    this.push(&this.internal_name(NIX_IN_SCOPE));
                     this.push(&if attrelem_raw_safe(vn) {
                         format!(".{}", vn)
                     } else {
@@ -144,10 +293,43 @@ impl Context<'_> {
         sctx: Option<StackCtx>,
         id: &Ident,
     ) -> Result<String, String> {
-        let categ = self.resolve_ident(id)?;
+        let categ = match (self.resolve_ident(id), &mut self.free_vars) {
+            (Err(_), Some(fv)) => {
+                fv.unbound.insert(id.as_str().to_string());
+                IdentCateg::WithScopeVar
+            }
+            (categ, fv) => {
+                let categ = categ?;
+                if let (IdentCateg::WithScopeVar, Some(fv)) = (&categ, fv) {
+                    fv.with_scoped.insert(id.as_str().to_string());
+                }
+                categ
+            }
+        };
+        // without a stack context, this is a declaration instead of a reference
+        if sctx.is_some() {
+            if let Some(idx) = self.lookup_var(id.as_str()) {
+                self.used_vars.insert(idx);
+            }
+        }
         Ok(self.translate_node_ident_raw(sctx, id.node().text_range(), id.as_str(), categ))
     }
 
+    /// unregisters all variables above `len`, warns about unused `let` bindings
+    /// if `let_keys` (which must match the registration order) is given
+    fn truncate_vars(&mut self, len: usize, let_keys: Option<&[(rnix::TextRange, String)]>) {
+        assert!(self.vars.len() >= len);
+        for (n, (txtrng, name)) in let_keys.unwrap_or_default().iter().enumerate() {
+            if !self.used_vars.contains(&(len + n)) && !name.starts_with('_') {
+                let lineno = self.txtrng_to_lineno(*txtrng);
+                self.warnings
+                    .push(format!("line {}: unused let binding {}", lineno, name));
+            }
+        }
+        self.vars.truncate(len);
+        self.used_vars.retain(|&i| i < len);
+    }
+
     fn translate_node_key_element_force_str(&mut self, node: &NixNode) -> TranslateResult {
         if let Some(name) = Ident::cast(node.clone()) {
             self.translate_node_ident_escape_str(&name);
@@ -239,7 +421,10 @@ impl Context<'_> {
                 self.translate_node_key_element_indexing(&kpfi)?;
                 self.push("=Object.create(null);");
             }
-            self.push(&format!("await {}._deepMerge(", NIX_OPERATORS));
+            self.push(&format!(
+                "await {}._deepMerge(",
+                self.internal_name(NIX_OPERATORS)
+            ));
             // this is a bit cheating because we directly override
             // parts of the attrset instead of round-tripping thru $`scope`.
             self.translate_node_scope_indexing(&kpfi, scope)?;
@@ -266,7 +451,25 @@ impl Context<'_> {
         // FIXME: how?
         if let Some(inhf) = inh.from() {
             let mut idents: Vec<_> = inh.idents().collect();
-            if idents.len() == 1 {
+            if idents.is_empty() {
+                // `inherit (x);` binds nothing, like in Nix the source
+                // is never forced, but it still gets checked.
+                self.lazyness_incoming(
+                    mksctx!(Nothing, Want),
+                    Tr::Forward,
+                    Tr::Flush,
+                    Ladj::Front,
+                    |this, sctx| {
+                        this.rtv(
+                            sctx,
+                            inhf.node().text_range(),
+                            inhf.inner(),
+                            "inner for inherit-from",
+                        )
+                    },
+                )?;
+                self.push(";");
+            } else if idents.len() == 1 {
                 let id = idents.remove(0);
                 self.translate_node_scope_indexing(id.node(), scope)?;
                 self.push("=");
@@ -291,10 +494,13 @@ impl Context<'_> {
                 let inhf_var = if let Some(x) = &use_inhtmp {
                     self.push("const ");
                     self.push(x);
-                    x
+                    x.clone()
                 } else {
-                    self.push("await (async ()=>{const nixInhR");
-                    "nixInhR"
+                    let x = self.fresh_name("nixInhR");
+                    self.push_ws("await (async ()=>{");
+                    self.push("const ");
+                    self.push(&x);
+                    x
                 };
                 self.push("=");
                 self.lazyness_incoming(
@@ -312,9 +518,15 @@ impl Context<'_> {
                     },
                 )?;
                 self.push(";");
+                // the source is shared, thus gets forced at most once,
+                // and only when one of the inherited values is needed
                 for id in idents {
                     self.translate_node_scope_indexing(id.node(), scope)?;
-                    self.push(&format!("={}.then(nixV=>nixV", inhf_var));
+                    self.push_ws("=nixBlti.PLazy.from(async ()=>(");
+                    self.push("await ");
+                    self.push(&inhf_var);
+                    self.push(")");
+                    self.stats.async_wraps += 1;
                     self.translate_node_ident_indexing(&id);
                     self.push(");");
                 }
@@ -350,28 +562,21 @@ impl Context<'_> {
             }
             return Ok(());
         }
+        if let Some(id) = duplicate_static_key(node) {
+            return Err(format!(
+                "line {}: attribute '{}' already defined",
+                self.txtrng_to_lineno(id.node().text_range()),
+                id.as_str()
+            ));
+        }
         let value_sctx = if values_lazy {
             mksctx!(Nothing, Want)
         } else {
             mksctx!(Nothing, Nothing)
         };
-        let getkeys = |node: &EH| {
-            let mut tmp: Vec<_> = node
-                .entries()
-                .flat_map(|i| i.key().and_then(|j| j.path().next()))
-                .chain(
-                    node.inherits()
-                        .flat_map(|i| i.idents())
-                        .map(|i| i.node().clone()),
-                )
-                .flat_map(|i| {
-                    Ident::cast(i).map(|j| (j.node().text_range(), j.as_str().to_string()))
-                })
-                .collect();
-            tmp.sort_unstable_by_key(|i| i.1.clone());
-            tmp.dedup_by_key(|i| i.1.clone());
-            tmp
-        };
+        // the `body` of legacy lets is referenced implicitly
+        let warn_unused = matches!(body, LetBody::Nix(_))
+            && node.node().kind() != rnix::SyntaxKind::NODE_LEGACY_LET;
         if scope != NIX_IN_SCOPE
             && matches!(body, LetBody::ExtractScope)
             && node.entries().all(|i| {
@@ -391,6 +596,7 @@ impl Context<'_> {
                 Ladj::Front,
                 |this, _| {
                     // optimization: use real object
+                    this.stats.fast_path_attrsets += 1;
                     this.push("Object.assign(Object.create(null),{");
                     let mut fi = true;
                     let mut handle_fi = move |this: &mut Self| {
@@ -442,104 +648,151 @@ impl Context<'_> {
             )
         } else if scope == NIX_IN_SCOPE
             && !matches!(body, LetBody::ExtractScope)
-            && node.entries().all(|i| {
-                i.value().is_some()
-                    && i.key()
-                        .and_then(|j| j.path().next())
-                        .map(|j| Ident::cast(j).is_some())
-                        == Some(true)
-            })
-            && node
-                .inherits()
-                .all(|i| i.from().is_none() || i.idents().count() == 1)
+            && let_vars_possible(node)
         {
             // use normal let-variables instead of attrset-like scopes if possible
             // to aid the garbage collector
             self.lazyness_incoming(body_sctx, Tr::Forward, Tr::Force, Ladj::Front, |this, _| {
-                let orig_vstkl = this.vars.len();
-                let mut fi = true;
-                this.push("{let ");
-                for (irng, i) in getkeys(node) {
-                    if fi {
-                        fi = false;
-                    } else {
-                        this.push(",");
-                    }
-                    // register variable names
-                    let idup = i.clone();
-                    this.vars.push((i, IdentCateg::LetLetVar));
-                    this.translate_node_ident_raw(None, irng, &*idup, IdentCateg::LetLetVar);
-                }
-                this.push(";");
-                for i in node.entries() {
-                    this.translate_node_kv(value_sctx, i, None)?;
-                }
-                for (n, i) in node.inherits().enumerate() {
-                    this.translate_node_inherit(
-                        value_sctx,
-                        i,
-                        None,
-                        Some(format!("nixInhR{}", n)),
-                    )?;
-                }
-                this.push("return ");
-                match body {
-                    LetBody::Nix(body) => this.translate_node(mksctx!(Want, Nothing), body)?,
-                    LetBody::ExtractScope => {
-                        this.push(&format!("{}[{}]", scope, NIX_EXTRACT_SCOPE))
-                    }
-                }
-                this.push(";}");
-                assert!(this.vars.len() >= orig_vstkl);
-                this.vars.truncate(orig_vstkl);
+                this.push("{");
+                this.translate_let_vars(value_sctx, node, body, warn_unused)?;
+                this.push("}");
                 Ok(())
             })
         } else {
             self.lazyness_incoming(body_sctx, Tr::Need, Tr::Forward, Ladj::Front, |this, _| {
-                this.push(&format!("(async {}=>{{", scope));
+                // `scope` is the unresolved name (see `internal_name`), the scopes of
+                // `let` and `rec` must shadow each other (see `mkScope`), other ones get
+                // unique names
+                let scope_name = if scope == NIX_IN_SCOPE {
+                    this.internal_name(scope)
+                } else {
+                    this.fresh_name(scope)
+                };
+                this.push(&format!("(async {}=>{{", scope_name));
                 let orig_vstkl = this.vars.len();
-                if scope == NIX_IN_SCOPE {
-                    for (_, i) in getkeys(node) {
-                        // register variable names
-                        this.vars.push((i, IdentCateg::LetInScopeVar));
-                    }
+                let keys = if scope == NIX_IN_SCOPE {
+                    let_keys(node)
+                } else {
+                    Vec::new()
+                };
+                for (_, i) in &keys {
+                    // register variable names
+                    this.vars.push((i.clone(), IdentCateg::LetInScopeVar));
                 }
                 for i in node.entries() {
-                    this.translate_node_kv(value_sctx, i, Some(scope))?;
+                    this.translate_node_kv(value_sctx, i, Some(&scope_name))?;
                 }
-                for (n, i) in node.inherits().enumerate() {
-                    this.translate_node_inherit(
-                        value_sctx,
-                        i,
-                        Some(scope),
-                        Some(format!("nixInhR{}", n)),
-                    )?;
+                for i in node.inherits() {
+                    let inhtmp = this.fresh_name("nixInhR");
+                    this.translate_node_inherit(value_sctx, i, Some(&scope_name), Some(inhtmp))?;
                 }
                 this.push("return ");
                 match body {
                     LetBody::Nix(body) => this.translate_node(mksctx!(Want, Nothing), body)?,
                     LetBody::ExtractScope => {
-                        this.push(&format!("{}[{}]", scope, NIX_EXTRACT_SCOPE))
+                        this.push(&format!("{}[{}]", scope_name, NIX_EXTRACT_SCOPE))
                     }
                 }
                 this.push(";})(nixBlti.mkScope(");
                 if scope == NIX_IN_SCOPE {
-                    this.push(NIX_IN_SCOPE);
+                    this.push(&scope_name);
                 }
-                assert!(this.vars.len() >= orig_vstkl);
-                this.vars.truncate(orig_vstkl);
+                this.truncate_vars(orig_vstkl, warn_unused.then(|| &keys[..]));
                 this.push("))");
                 Ok(())
             })
         }
     }
 
+    /// emits the statements of a let-var block (`let ...;...;return ...;`);
+    /// if the body is another `let` whose variables don't shadow any JS variable,
+    /// it gets flattened into the same block instead of nesting another thunk.
+    fn translate_let_vars<EH: EntryHolder>(
+        &mut self,
+        value_sctx: StackCtx,
+        node: &EH,
+        body: LetBody,
+        warn_unused: bool,
+    ) -> TranslateResult {
+        let orig_vstkl = self.vars.len();
+        let keys = let_keys(node);
+        let mut fi = true;
+        self.push("let ");
+        for (irng, i) in &keys {
+            if fi {
+                fi = false;
+            } else {
+                self.push(",");
+            }
+            // register variable names
+            let categ = match let_static_keys(node, i) {
+                Some(keys) => IdentCateg::LetLetAttrs(keys),
+                None => IdentCateg::LetLetVar,
+            };
+            self.vars.push((i.clone(), categ.clone()));
+            self.translate_node_ident_raw(None, *irng, i, categ);
+        }
+        self.push(";");
+        for i in node.entries() {
+            self.translate_node_kv(value_sctx, i, None)?;
+        }
+        for i in node.inherits() {
+            let inhtmp = self.fresh_name("nixInhR");
+            self.translate_node_inherit(value_sctx, i, None, Some(inhtmp))?;
+        }
+        let flat = match &body {
+            LetBody::Nix(body) => LetIn::cast(body.clone()).filter(|inner| {
+                inner.body().is_some()
+                    && (inner.entries().next().is_some() || inner.inherits().next().is_some())
+                    && let_vars_possible(inner)
+                    && let_keys(inner).iter().all(|(_, k)| {
+                        !matches!(
+                            self.lookup_var(k).map(|idx| &self.vars[idx].1),
+                            Some(
+                                IdentCateg::LambdaArg
+                                    | IdentCateg::LetLetVar
+                                    | IdentCateg::LetLetAttrs(_)
+                            )
+                        )
+                    })
+            }),
+            LetBody::ExtractScope => None,
+        };
+        if let Some(inner) = flat {
+            self.flatten_let = true;
+            self.translate_node(value_sctx, inner.node().clone())?;
+        } else {
+            self.push("return ");
+            match body {
+                LetBody::Nix(body) => self.translate_node(mksctx!(Want, Nothing), body)?,
+                LetBody::ExtractScope => self.push(&format!(
+                    "{}[{}]",
+                    self.internal_name(NIX_IN_SCOPE),
+                    NIX_EXTRACT_SCOPE
+                )),
+            }
+            self.push(";");
+        }
+        self.truncate_vars(orig_vstkl, warn_unused.then(|| &keys[..]));
+        Ok(())
+    }
+
     fn translate_node(&mut self, sctx: StackCtx, node: NixNode) -> TranslateResult {
         if node.kind().is_trivia() {
             return Ok(());
         }
 
         let txtrng = node.text_range();
+        if self.opts.trace_codegen {
+            eprintln!(
+                "nix2js trace: {:?} {:?} await={:?} lazy={:?}",
+                node.kind(),
+                txtrng,
+                sctx.await_st,
+                sctx.lazy_st
+            );
+        }
+        self.stats.node_count += 1;
         self.snapshot_pos(txtrng.start());
         let x = match ParsedType::try_from(node) {
             Err(e) => {
@@ -554,8 +807,23 @@ impl Context<'_> {
         };
         use ParsedType as Pt;
 
+        // the parser might misplace nodes while recovering from syntax errors
+        if self.opts.recover_parse_errors
+            && matches!(
+                x,
+                Pt::Key(_) | Pt::KeyValue(_) | Pt::Pattern(_) | Pt::PatBind(_) | Pt::PatEntry(_)
+            )
+        {
+            let msg = format!("line {}: parse error", self.txtrng_to_lineno(txtrng));
+            return self.translate_parse_error(sctx, &msg);
+        }
+
         match x {
             Pt::Apply(app) => {
+                if let Some(folded) = self.fold_from_toml(&app) {
+                    self.push(&folded);
+                    return Ok(());
+                }
                 self.lazyness_incoming(sctx, Tr::Need, Tr::Need, Ladj::Front, |this, _sctx| {
                     this.push("(");
                     this.rtv(
@@ -581,7 +849,7 @@ impl Context<'_> {
                     // NOTE: we rely on the impl.detail of lazyness_incoming
                     // here that no parens are inserted between => and { ... }
                     this.push("{await ");
-                    this.push(NIX_BUILTINS_RT);
+                    this.push(&this.internal_name(NIX_BUILTINS_RT));
                     this.push(".assert(");
                     let cond = if let Some(cond) = art.condition() {
                         cond
@@ -591,27 +859,31 @@ impl Context<'_> {
                             this.txtrng_to_lineno(txtrng),
                         ));
                     };
+                    // long conditions get abbreviated, the line is enough to find them
                     this.push(&escape_str(&format!(
                         "line {}: {}",
                         this.txtrng_to_lineno(txtrng),
-                        cond.text()
+                        this.snippet(cond.text_range())
                     )));
-                    this.push(",");
+                    this.push(")(");
                     this.translate_node(mksctx!(Nothing, Nothing), cond)?;
-                    this.push("); return (");
+                    this.push_ws("); return (");
                     this.rtv(
                         mksctx!(Want, Nothing),
                         txtrng,
                         art.body(),
                         "body for assert",
                     )?;
-                    this.push("); }");
+                    this.push_ws("); }");
                     Ok(())
                 })?;
             }
 
             Pt::AttrSet(ars) => {
-                let scope = if ars.recursive() {
+                // NOTE: non-recursive attrsets get uniquely named scopes (see `fresh_name`),
+                // their members are never in scope of the values anyway.
+                // recursive attrsets without self-references can use the fast path
+                let scope = if ars.recursive() && !rec_is_trivial(&ars) {
                     NIX_IN_SCOPE
                 } else {
                     "nixAttrsScope"
@@ -650,6 +922,63 @@ impl Context<'_> {
                         }
                         self.push(")");
                     }
+                    Bok::And | Bok::Implication | Bok::Or
+                        if matches!(bo.lhs(), Some(i) if self.is_provably_bool(&i))
+                            && matches!(bo.rhs(), Some(i) if self.is_provably_bool(&i)) =>
+                    {
+                        // both sides always yield booleans (or throw),
+                        // so we can use the JS short-circuit operators directly
+                        self.lazyness_incoming(
+                            sctx,
+                            Tr::Flush,
+                            Tr::Flush,
+                            Ladj::Front,
+                            |this, _| {
+                                let mysctx = mksctx!(Want, Nothing);
+                                this.push("(");
+                                if let Bok::Implication = op {
+                                    this.push("!");
+                                }
+                                this.rtv(mysctx, txtrng, bo.lhs(), "lhs for binop")?;
+                                this.push(if let Bok::And = op { "&&" } else { "||" });
+                                this.rtv(mysctx, txtrng, bo.rhs(), "rhs for binop")?;
+                                this.push(")");
+                                TranslateResult::Ok(())
+                            },
+                        )?;
+                    }
+                    Bok::And | Bok::Implication | Bok::Or => {
+                        // the rhs is passed lazily, because it must only be
+                        // evaluated if the lhs doesn't already decide the result.
+                        self.lazyness_incoming(
+                            sctx,
+                            Tr::Need,
+                            Tr::Flush,
+                            Ladj::Front,
+                            |this, _| {
+                                this.push(&format!(
+                                    "{}.{:?}(",
+                                    this.internal_name(NIX_OPERATORS),
+                                    op
+                                ));
+                                this.rtv(
+                                    mksctx!(Nothing, Nothing),
+                                    txtrng,
+                                    bo.lhs(),
+                                    "lhs for binop",
+                                )?;
+                                this.push(",");
+                                this.rtv(
+                                    mksctx!(Nothing, Want),
+                                    txtrng,
+                                    bo.rhs(),
+                                    "rhs for binop",
+                                )?;
+                                this.push(")");
+                                TranslateResult::Ok(())
+                            },
+                        )?;
+                    }
                     _ => {
                         self.lazyness_incoming(
                             sctx,
@@ -658,7 +987,11 @@ impl Context<'_> {
                             Ladj::Front,
                             |this, _| {
                                 let mysctx = mksctx!(Nothing, Nothing);
-                                this.push(&format!("{}.{:?}(", NIX_OPERATORS, op));
+                                this.push(&format!(
+                                    "{}.{:?}(",
+                                    this.internal_name(NIX_OPERATORS),
+                                    op
+                                ));
                                 this.rtv(mysctx, txtrng, bo.lhs(), "lhs for binop")?;
                                 this.push(",");
                                 this.rtv(mysctx, txtrng, bo.rhs(), "rhs for binop")?;
@@ -674,8 +1007,12 @@ impl Context<'_> {
                 self.rtv(sctx, txtrng, d.inner(), "inner for dynamic (key)")?;
             }
 
-            // should be catched by `parsed.errors()...` in `translate(_)`
-            Pt::Error(_) => unreachable!(),
+            // only reachable with `TranslateOptions::recover_parse_errors`,
+            // otherwise `parsed.errors()` is checked in `translate_with_options`
+            Pt::Error(_) => {
+                let msg = format!("line {}: parse error", self.txtrng_to_lineno(txtrng));
+                self.translate_parse_error(sctx, &msg)?;
+            }
 
             Pt::Ident(id) => {
                 self.translate_node_ident(Some(sctx), &id)?;
@@ -716,7 +1053,27 @@ impl Context<'_> {
                 };
                 // FIXME: use guard to truncate vars
                 let cur_lamstk = self.vars.len();
-                const BODY_SCTX: StackCtx = mksctx!(Want, Nothing);
+                // the body is returned from an async function, which flushes it
+                const BODY_SCTX: StackCtx = mksctx!(Did, Nothing);
+                let is_pattern = if let Some(y) = Pattern::cast(argx.clone()) {
+                    // record the formals, needed for `builtins.functionArgs`
+                    let formals: Vec<_> = y
+                        .entries()
+                        .flat_map(|i| {
+                            let has_default = i.default().is_some();
+                            i.name()
+                                .map(|z| format!("{}:{}", escape_str(z.as_str()), has_default))
+                        })
+                        .collect();
+                    self.push(&format!(
+                        "{}({{{}}},",
+                        NIX_SET_FUNCTION_ARGS,
+                        formals.join(",")
+                    ));
+                    true
+                } else {
+                    false
+                };
                 self.push("(async ");
                 if let Some(y) = Ident::cast(argx.clone()) {
                     let yas = y.as_str();
@@ -724,17 +1081,18 @@ impl Context<'_> {
                     self.translate_node_ident(None, &y)?;
                     self.push("=>(");
                     self.rtv(BODY_SCTX, txtrng, lam.body(), "body for lambda")?;
-                    assert!(self.vars.len() >= cur_lamstk);
-                    self.vars.truncate(cur_lamstk);
+                    self.truncate_vars(cur_lamstk, None);
                     self.push(")");
                 } else if let Some(y) = Pattern::cast(argx) {
+                    // `y.at()` covers both `args@{ ... }` and `{ ... }@args`
                     let argname = if let Some(z) = y.at() {
                         self.vars
                             .push((z.as_str().to_string(), IdentCateg::LambdaArg));
                         self.translate_node_ident(None, &z)?
                     } else {
-                        self.push(NIX_LAMBDA_BOUND);
-                        NIX_LAMBDA_BOUND.to_string()
+                        let x = self.internal_name(NIX_LAMBDA_BOUND);
+                        self.push(&x);
+                        x
                     };
                     // register var names
                     let mut entries = Vec::new();
@@ -758,7 +1116,11 @@ impl Context<'_> {
                         self.translate_node_ident(None, &z)?;
                         // NOTE: it should be unnecessary to insert `await` here,
                         // instead, it is inserted at the usage sites.
-                        self.push(&format!("={}._lambdaA2chk({},", NIX_OPERATORS, argname));
+                        self.push(&format!(
+                            "={}._lambdaA2chk({},",
+                            self.internal_name(NIX_OPERATORS),
+                            argname
+                        ));
                         self.translate_node_ident_escape_str(&z);
                         if let Some(zdfl) = dfl {
                             self.push(",");
@@ -770,13 +1132,15 @@ impl Context<'_> {
 
                     self.push("return ");
                     self.rtv(BODY_SCTX, txtrng, lam.body(), "body for lambda")?;
-                    assert!(self.vars.len() >= cur_lamstk);
-                    self.vars.truncate(cur_lamstk);
+                    self.truncate_vars(cur_lamstk, None);
                     self.push("}");
                 } else {
                     return Err(format!("lambda ({:?}) with invalid argument", lam));
                 }
                 self.push(")");
+                if is_pattern {
+                    self.push(")");
+                }
             }
 
             Pt::LegacyLet(l) => self.translate_let(
@@ -811,18 +1175,19 @@ impl Context<'_> {
                 NIX_IN_SCOPE,
             )?,
 
-            Pt::LetIn(l) => self.translate_let(
-                sctx,
-                true,
-                &l,
-                LetBody::Nix(l.body().ok_or_else(|| {
+            Pt::LetIn(l) => {
+                let body = LetBody::Nix(l.body().ok_or_else(|| {
                     format!(
                         "line {}: let ... in ... without body",
                         self.txtrng_to_lineno(l.node().text_range())
                     )
-                })?),
-                NIX_IN_SCOPE,
-            )?,
+                })?);
+                if std::mem::take(&mut self.flatten_let) {
+                    self.translate_let_vars(sctx, &l, body, true)?;
+                } else {
+                    self.translate_let(sctx, true, &l, body, NIX_IN_SCOPE)?;
+                }
+            }
 
             Pt::List(l) => {
                 self.lazyness_incoming(sctx, Tr::Forward, Tr::Flush, Ladj::Front, |this, _| {
@@ -834,7 +1199,9 @@ impl Context<'_> {
                         } else {
                             this.push(",");
                         }
-                        this.translate_node(mksctx!(Nothing, Nothing), i)?;
+                        // list elements are never forced by list operations
+                        // (e.g. `++`, `length`), only by accessing them.
+                        this.translate_node(mksctx!(Nothing, Want), i)?;
                     }
                     this.push("]");
                     TranslateResult::Ok(())
@@ -870,7 +1237,17 @@ impl Context<'_> {
             Pt::PatBind(p) => unreachable!("standalone pattern @ bind not supported: {:?}", p),
             Pt::PatEntry(p) => unreachable!("standalone pattern entry not supported: {:?}", p),
 
-            Pt::Root(r) => self.rtv(sctx, txtrng, r.inner(), "inner for root")?,
+            Pt::Root(r) => {
+                // the parser reports trailing input as error, but don't silently drop
+                // anything if it ever produces a root with multiple expressions
+                if let Some(extra) = r.node().children().nth(1) {
+                    return Err(format!(
+                        "line {}: unexpected expression after the root expression",
+                        self.txtrng_to_lineno(extra.text_range())
+                    ));
+                }
+                self.rtv(sctx, txtrng, r.inner(), "inner for root")?
+            }
 
             Pt::Select(sel) => {
                 let idx = if let Some(idx) = sel.index() {
@@ -894,6 +1271,22 @@ impl Context<'_> {
                 } else {
                     return Err(format!("{:?}: set for select missing", txtrng));
                 };
+                // `builtins.X` where X is fixed via the options
+                let folded = match (Ident::cast(slt.clone()), Ident::cast(idx.clone())) {
+                    (Some(set), Some(key))
+                        if matches!(
+                            self.resolve_ident(&set),
+                            Ok(IdentCateg::Literal(NIX_BUILTINS_RT))
+                        ) =>
+                    {
+                        self.fold_builtin(key.as_str())
+                    }
+                    _ => None,
+                };
+                if let Some(lit) = folded {
+                    self.snapshot_ident(txtrng, |this| this.push(&lit));
+                    return Ok(());
+                }
                 // TODO: improve this mess
                 let (xsctx, xtr) = if is_wellknown {
                     (mksctx!(Nothing, Nothing), Tr::Forward)
@@ -911,7 +1304,7 @@ impl Context<'_> {
                 use rnix::value::StrPart as Sp;
                 // NOTE: we do not need to honor lazyness if we just put a
                 // literal string here
-                match s.parts()[..] {
+                match str_parts(&s)[..] {
                     [] => self.push("\"\""),
                     [Sp::Literal(ref lit)] => self.push(&escape_str(lit)),
                     ref sxs => self.lazyness_incoming(
@@ -970,7 +1363,11 @@ impl Context<'_> {
                 match uo.operator() {
                     Uok::Invert | Uok::Negate => {}
                 }
-                self.push(&format!("{}.u_{:?}(", NIX_OPERATORS, uo.operator()));
+                self.push(&format!(
+                    "{}.u_{:?}(",
+                    self.internal_name(NIX_OPERATORS),
+                    uo.operator()
+                ));
                 self.rtv(
                     mksctx!(Nothing, Nothing),
                     txtrng,
@@ -985,16 +1382,37 @@ impl Context<'_> {
                     use rnix::value::Value as NixVal;
                     use serde_json::value::{Number as JsNum, Value as JsVal};
                     let jsvs = match x {
-                        NixVal::Float(flt) => {
-                            JsVal::Number(JsNum::from_f64(flt).expect("unrepr-able float"))
-                                .to_string()
-                        }
-                        NixVal::Integer(int) => JsVal::Number(int.into()).to_string(),
+                        // out-of-range literals (e.g. `1.0e400`) are rejected like in Nix
+                        NixVal::Float(flt) => match JsNum::from_f64(flt) {
+                            Some(flt) => JsVal::Number(flt).to_string(),
+                            None => {
+                                return Err(format!(
+                                    "line {}: invalid float '{}'",
+                                    self.txtrng_to_lineno(txtrng),
+                                    &self.inp[txtrng]
+                                ))
+                            }
+                        },
+                        // integers are `BigInt`s, so that integral floats stay floats
+                        NixVal::Integer(int) => format!("{}n", int),
                         NixVal::String(s) => JsVal::String(s).to_string(),
                         NixVal::Path(anch, path) => {
+                            let method = match &self.opts.path_mode {
+                                PathMode::Store => "export",
+                                PathMode::String => {
+                                    self.push(&escape_str(&self.inp[txtrng]));
+                                    return Ok(());
+                                }
+                                PathMode::Custom(method) => method,
+                            };
                             format!(
-                                "{}.export({},{})",
+                                "{}{}({},{})",
                                 NIX_RUNTIME,
+                                if attrelem_raw_safe(method) {
+                                    format!(".{}", method)
+                                } else {
+                                    format!("[{}]", escape_str(method))
+                                },
                                 escape_str(&format!("{:?}", anch)),
                                 escape_str(&path),
                             )
@@ -1012,16 +1430,32 @@ impl Context<'_> {
             },
 
             Pt::With(with) => {
-                self.push(&format!("(async {}=>(", NIX_IN_SCOPE));
-                self.with_stack += 1;
+                self.push(&format!("(async {}=>(", self.internal_name(NIX_IN_SCOPE)));
+                let ns = with
+                    .namespace()
+                    .and_then(Ident::cast)
+                    .and_then(|id| self.lookup_var(id.as_str()))
+                    .filter(|&idx| matches!(self.vars[idx].1, IdentCateg::LetLetAttrs(_)));
+                if ns.is_none() {
+                    let lineno = self.txtrng_to_lineno(txtrng);
+                    self.warnings.push(format!(
+                        "line {}: namespace of 'with' isn't statically known, \
+                         identifiers inside of it can't be checked",
+                        lineno
+                    ));
+                }
+                self.with_stack.push(ns);
                 self.rtv(
                     mksctx!(Want, Nothing),
                     txtrng,
                     with.body(),
                     "body for 'with' scope",
                 )?;
-                self.with_stack -= 1;
-                self.push(&format!("))(nixBlti.mkScopeWith({},", NIX_IN_SCOPE));
+                self.with_stack.pop();
+                self.push(&format!(
+                    "))(nixBlti.mkScopeWith({},",
+                    self.internal_name(NIX_IN_SCOPE)
+                ));
                 self.rtv(
                     mksctx!(Want, Nothing),
                     txtrng,
@@ -1036,15 +1470,618 @@ impl Context<'_> {
     }
 }
 
+/// how path literals get emitted
+#[derive(Clone, Debug, Default)]
+pub enum PathMode {
+    /// `nixRt.export(anchor, path)`, which should export the path into the store
+    #[default]
+    Store,
+
+    /// the path as written in the source, as a plain string
+    String,
+
+    /// like `Store`, but calls the given runtime method instead of `export`
+    Custom(String),
+}
+
+/// how `builtins.trace` gets emitted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceMode {
+    /// the runtime passes the message to `nixRt.trace(msg)`
+    #[default]
+    Runtime,
+
+    /// `builtins.trace` becomes an identity on its second argument,
+    /// the message doesn't get forced or passed to the runtime
+    Suppress,
+}
+
+/// lets embedders extend the set of builtins without shadowing the regular ones,
+/// consulted for identifiers which aren't bound otherwise (before `with` scopes).
+///
+/// ```
+/// use nix2js::ResolvedBuiltin;
+///
+/// #[derive(Debug)]
+/// struct MyBuiltins;
+///
+/// impl nix2js::BuiltinResolver for MyBuiltins {
+///     fn resolve(&self, name: &str) -> Option<ResolvedBuiltin> {
+///         (name == "myFetch").then_some(ResolvedBuiltin::Builtin("__fetchurl"))
+///     }
+/// }
+///
+/// let opts = nix2js::TranslateOptions {
+///     builtin_resolver: Some(std::sync::Arc::new(MyBuiltins)),
+///     ..Default::default()
+/// };
+/// let out = nix2js::translate_with_options("myFetch \"x\"", "a.nix", &opts).unwrap();
+/// assert!(out.js.contains("nixBltiRT.fetchurl"));
+/// assert!(nix2js::translate("myFetch \"x\"", "a.nix").is_err());
+/// ```
+pub trait BuiltinResolver: std::fmt::Debug + Send + Sync {
+    /// the translation of the identifier `name`, if it is provided by the embedder
+    fn resolve(&self, name: &str) -> Option<ResolvedBuiltin>;
+}
+
+/// what an identifier provided by a [`BuiltinResolver`] gets translated to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResolvedBuiltin {
+    /// the name of a builtin, emitted as member of `nixBltiRT`
+    /// (with or without the `__` prefix)
+    Builtin(&'static str),
+    /// a JS expression, emitted verbatim
+    Literal(&'static str),
+}
+
+/// options which influence the generated code and source map
+#[derive(Clone, Debug)]
+pub struct TranslateOptions {
+    /// prefix for the entries of `sources` in the source map,
+    /// emitted as the `sourceRoot` field if set
+    pub source_root: Option<String>,
+
+    /// if set, `builtins.storeDir` gets emitted as this literal string
+    /// instead of being looked up in the runtime
+    pub store_dir: Option<String>,
+
+    /// if set, `builtins.nixVersion` gets emitted as this literal string
+    pub nix_version: Option<String>,
+
+    /// if set, `builtins.langVersion` gets emitted as this literal integer
+    pub lang_version: Option<u32>,
+
+    /// if set, the source map gets an additional `x_generator` field
+    /// containing the version of nix2js which produced it
+    pub generator_marker: bool,
+
+    /// if set, a warning is emitted when the generated JS is larger
+    /// than the given multiple of the input size (purely advisory)
+    pub output_ratio_warn: Option<f64>,
+
+    /// if set, the output only consists of the translated expression,
+    /// without the [`runtime_preamble`] and `return`; the caller is then
+    /// responsible for bringing `nixOp`, `nixBltiRT` and `nixInScope` into scope
+    pub omit_preamble: bool,
+
+    /// if set, gets emitted as leading line comments (e.g. for license headers)
+    pub banner: Option<String>,
+
+    /// if set, the output starts with `// @ts-nocheck` and `/* eslint-disable */`
+    /// (before the banner), for consumers which type-check or lint JS files
+    pub ts_nocheck: bool,
+
+    /// if set, no source map gets generated (`TranslateOutput::map` is empty)
+    pub no_source_map: bool,
+
+    /// how path literals get emitted
+    pub path_mode: PathMode,
+
+    /// how `builtins.trace` gets emitted
+    pub trace_mode: TraceMode,
+
+    /// if set, the output is an ES module which exports the translated code as
+    /// `export const NAME=async function(nixRt,nixBlti){...}`;
+    /// `NAME` must be a valid JS identifier (ASCII only), and not a reserved word
+    pub entry_export: Option<String>,
+
+    /// the prefix of all generated identifiers (`nixInScope`, `nixOp`, `nixBltiRT`,
+    /// `nix__` for translated identifiers, temporaries, ...), except the parameters
+    /// `nixRt` and `nixBlti`; must be a valid JS identifier, defaults to `nix`.
+    /// See also [`runtime_preamble_prefixed`]
+    pub name_prefix: String,
+
+    /// if set, the readability whitespace is omitted and generated temporaries
+    /// get shorter names; the source map is still accurate
+    pub minify: bool,
+
+    /// emit a JSDoc block describing `nixRt` and `nixBlti` before the
+    /// `entry_export` wrapper (ignored otherwise)
+    pub jsdoc: bool,
+
+    /// resolves additional builtins, see [`BuiltinResolver`]
+    pub builtin_resolver: Option<std::sync::Arc<dyn BuiltinResolver>>,
+
+    /// translate inputs with syntax errors as far as possible (e.g. for editors):
+    /// unparsable or missing expressions become calls to `nixRt.parseError(msg)`
+    /// (when evaluated), the syntax errors are reported as warnings
+    pub recover_parse_errors: bool,
+
+    /// experimental: with `entry_export`, if the root is a non-recursive attrset
+    /// with plain keys (valid JS identifiers, no `inherit`), every key gets its own
+    /// export `export const KEY=async function(nixRt,nixBlti){...}` instead, so
+    /// bundlers can tree-shake unused entries; other roots use `entry_export`
+    pub export_keys: bool,
+
+    /// with `entry_export` (and a single export), append a Web Worker entry point:
+    /// the worker evaluates the export when it receives a message, using the
+    /// message data as `nixRt` (only plain data, e.g. `storeDir`, can be posted),
+    /// and replies with `{result}` (serialized via `builtins.toJSON`) or `{error}`
+    ///
+    /// ```
+    /// let opts = nix2js::TranslateOptions {
+    ///     entry_export: Some("main".to_string()),
+    ///     web_worker: true,
+    ///     no_source_map: true,
+    ///     ..Default::default()
+    /// };
+    /// let out = nix2js::translate_with_options("1 + 1", "a.nix", &opts).unwrap();
+    /// assert!(out.js.starts_with("export const main=async function(nixRt,nixBlti){"));
+    /// assert!(out.js.contains("\nimport * as nixBlti from \"nix-builtins\";\n"));
+    /// assert!(out.js.contains("self.onmessage=async function(ev){"));
+    /// assert!(out.js.contains(".toJSON(main(nixRt,nixBlti))"));
+    /// ```
+    pub web_worker: bool,
+
+    /// log every translated node (kind, text range and the await/lazy state
+    /// of its `StackCtx`) to stderr; meant for debugging the transpiler
+    pub trace_codegen: bool,
+}
+
+impl Default for TranslateOptions {
+    fn default() -> Self {
+        Self {
+            source_root: Default::default(),
+            store_dir: Default::default(),
+            nix_version: Default::default(),
+            lang_version: Default::default(),
+            generator_marker: Default::default(),
+            output_ratio_warn: Default::default(),
+            omit_preamble: Default::default(),
+            banner: Default::default(),
+            ts_nocheck: Default::default(),
+            no_source_map: Default::default(),
+            path_mode: Default::default(),
+            trace_mode: Default::default(),
+            entry_export: Default::default(),
+            name_prefix: NIX_NAME_PREFIX.to_string(),
+            minify: Default::default(),
+            jsdoc: Default::default(),
+            builtin_resolver: Default::default(),
+            recover_parse_errors: Default::default(),
+            export_keys: Default::default(),
+            web_worker: Default::default(),
+            trace_codegen: Default::default(),
+        }
+    }
+}
+
+/// the result of a successful translation
+#[derive(Clone, Debug)]
+pub struct TranslateOutput {
+    pub js: String,
+    /// empty if `TranslateOptions::no_source_map` is set
+    pub map: String,
+    /// non-fatal diagnostics, e.g. unused `let` bindings
+    pub warnings: Vec<String>,
+    pub stats: TranslateStats,
+}
+
+/// metrics collected during translation
+///
+/// ```
+/// let src = "let a = 1; in let b = 2; in a + b";
+/// let out = nix2js::translate_with_options(src, "a.nix", &Default::default()).unwrap();
+/// let stats = out.stats;
+/// assert_eq!(stats.input_bytes, 33);
+/// assert_eq!(stats.output_bytes, out.js.len());
+/// // both `let`s (the inner one gets flattened), `1`, `2`, `a + b`, `a`, `b`
+/// // and the enclosing root node
+/// assert_eq!(stats.node_count, 8);
+/// assert_eq!(stats.async_wraps, 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TranslateStats {
+    pub input_bytes: usize,
+    pub output_bytes: usize,
+    /// number of (non-trivia) syntax nodes translated
+    pub node_count: usize,
+    /// number of entries in the `names` of the source map
+    pub distinct_names: usize,
+    /// number of segments in the `mappings` of the source map
+    pub map_segments: usize,
+    /// attrsets emitted as plain objects instead of scopes
+    pub fast_path_attrsets: usize,
+    /// number of emitted `PLazy` thunks
+    pub async_wraps: usize,
+}
+
+/// a builtin known to the translator, see [`list_builtins`]
+#[derive(Clone, Debug)]
+pub struct BuiltinInfo {
+    /// the name inside of `builtins`
+    pub name: &'static str,
+    /// also in scope without `builtins.` (e.g. `map`)
+    pub global: bool,
+    /// depends on the environment (files, network, time, ...)
+    pub impure: bool,
+    /// can be evaluated at translation time (depending on the arguments or options)
+    pub folded: bool,
+}
+
+/// all builtins known to the translator, sorted by name
+pub fn list_builtins() -> Vec<BuiltinInfo> {
+    let mut ret: Vec<_> = DFL_VARS
+        .iter()
+        .filter_map(|(name, categ)| match categ {
+            IdentCateg::AlBuiltin(_) => {
+                let bname = name.strip_prefix("__").unwrap_or(name);
+                Some(BuiltinInfo {
+                    name: bname,
+                    global: bname == *name,
+                    impure: IMPURE_BUILTINS.contains(&bname),
+                    folded: FOLDED_BUILTINS.contains(&bname),
+                })
+            }
+            _ => None,
+        })
+        .collect();
+    ret.sort_by_key(|i| i.name);
+    ret
+}
+
+/// the JS statements which set up the aliases used by the translated code,
+/// expects `nixBlti` and `nixRt` to be in scope
+pub fn runtime_preamble() -> String {
+    runtime_preamble_prefixed(NIX_NAME_PREFIX)
+}
+
+/// like [`runtime_preamble`], but for `TranslateOptions::name_prefix`
+pub fn runtime_preamble_prefixed(prefix: &str) -> String {
+    let name = |i: &str| format!("{}{}", prefix, i.strip_prefix("nix").unwrap());
+    format!(
+        "let {}=nixBlti.nixOp;let {}=nixBlti.initRtDep(nixRt);let {}=nixBlti.mkScopeWith();",
+        name(NIX_OPERATORS),
+        name(NIX_BUILTINS_RT),
+        name(NIX_IN_SCOPE)
+    )
+}
+
+// only static keys are part of the recursive scope,
+// dynamic keys (`${expr} = ...`) can't be referenced by name (like in Nix)
+fn let_keys<EH: EntryHolder>(node: &EH) -> Vec<(rnix::TextRange, String)> {
+    let mut tmp: Vec<_> = node
+        .entries()
+        .flat_map(|i| i.key().and_then(|j| j.path().next()))
+        .chain(
+            node.inherits()
+                .flat_map(|i| i.idents())
+                .map(|i| i.node().clone()),
+        )
+        .flat_map(|i| Ident::cast(i).map(|j| (j.node().text_range(), j.as_str().to_string())))
+        .collect();
+    tmp.sort_unstable_by_key(|i| i.1.clone());
+    tmp.dedup_by_key(|i| i.1.clone());
+    tmp
+}
+
+// checks if a `rec` attrset can be treated like a non-recursive one, because
+// all keys are static and no value mentions the name of any of them
+fn rec_is_trivial<EH: EntryHolder>(node: &EH) -> bool {
+    let keys: std::collections::HashSet<_> = let_keys(node).into_iter().map(|i| i.1).collect();
+    node.entries().all(|i| {
+        matches!(i.key(), Some(j) if j.path().count() == 1
+            && j.path().next().and_then(Ident::cast).is_some())
+    }) && !node
+        .entries()
+        .flat_map(|i| i.value())
+        .chain(
+            node.inherits()
+                .flat_map(|i| i.from())
+                .map(|i| i.node().clone()),
+        )
+        .flat_map(|i| i.descendants())
+        .flat_map(Ident::cast)
+        .any(|i| keys.contains(i.as_str()))
+}
+
+// the first repeated single-segment static key (e.g. `{ a = 1; a = 2; }`), if any
+fn duplicate_static_key<EH: EntryHolder>(node: &EH) -> Option<Ident> {
+    let mut seen = std::collections::HashSet::new();
+    node.entries()
+        .filter(|i| i.key().map(|j| j.path().count()) == Some(1))
+        .flat_map(|i| i.key().and_then(|j| j.path().next()))
+        .chain(
+            node.inherits()
+                .flat_map(|i| i.idents())
+                .map(|i| i.node().clone()),
+        )
+        .flat_map(Ident::cast)
+        .find(|i| !seen.insert(i.as_str().to_string()))
+}
+
+/// if `name` is an ASCII-only JS identifier (which may still be in `JS_RESERVED`)
+fn is_js_ident(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|i: char| i.is_ascii_digit())
+        && name
+            .chars()
+            .all(|i| i.is_ascii_alphanumeric() || i == '_' || i == '$')
+}
+
+const JS_RESERVED: &[&str] = &[
+    "arguments",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+// the entries of the root attrset (see `TranslateOptions::export_keys`),
+// if every one of them can become a separate export
+fn root_exports(root: &NixNode) -> Option<Vec<(String, NixNode)>> {
+    let aset = AttrSet::cast(Root::cast(root.clone())?.inner()?)?;
+    if aset.recursive() || aset.inherits().next().is_some() || duplicate_static_key(&aset).is_some()
+    {
+        return None;
+    }
+    let mut ret = Vec::new();
+    for i in aset.entries() {
+        let key = i.key()?;
+        if key.path().count() != 1 {
+            return None;
+        }
+        let name = Ident::cast(key.path().next()?)?.as_str().to_string();
+        if !is_js_ident(&name) || JS_RESERVED.contains(&name.as_str()) {
+            return None;
+        }
+        ret.push((name, i.value()?));
+    }
+    if ret.is_empty() {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+// keys of `name = { ... };`, if all of them are statically known
+fn let_static_keys<EH: EntryHolder>(node: &EH, name: &str) -> Option<std::rc::Rc<[String]>> {
+    let mut ents = node.entries().filter(|i| {
+        matches!(
+            i.key().and_then(|j| j.path().next()).and_then(Ident::cast),
+            Some(j) if j.as_str() == name
+        )
+    });
+    let ent = ents.next()?;
+    if ents.next().is_some() || ent.key()?.path().count() != 1 {
+        return None;
+    }
+    let aset = AttrSet::cast(ent.value()?)?;
+    let mut keys = Vec::new();
+    for i in aset.entries() {
+        keys.push(Ident::cast(i.key()?.path().next()?)?.as_str().to_string());
+    }
+    keys.extend(
+        aset.inherits()
+            .flat_map(|i| i.idents())
+            .map(|i| i.as_str().to_string()),
+    );
+    Some(keys.into())
+}
+
+// if the scope can be represented using plain JS `let` variables
+fn let_vars_possible<EH: EntryHolder>(node: &EH) -> bool {
+    node.entries().all(|i| {
+        i.value().is_some()
+            && i.key()
+                .and_then(|j| j.path().next())
+                .map(|j| Ident::cast(j).is_some())
+                == Some(true)
+    }) && node
+        .inherits()
+        .all(|i| i.from().is_none() || i.idents().count() == 1)
+}
+
+fn render_parse_errors(s: &str, errs: Vec<rnix::parser::ParseError>) -> Vec<String> {
+    use rnix::parser::ParseError as Pe;
+    let mut line_cache = linetrack::LineCache::new(s);
+    errs.into_iter()
+        .map(|e| {
+            let pos = match &e {
+                Pe::Unexpected(rng)
+                | Pe::UnexpectedExtra(rng)
+                | Pe::UnexpectedWanted(_, rng, _)
+                | Pe::UnexpectedDoubleBind(rng)
+                | Pe::DuplicatedArgs(rng, _) => usize::from(rng.start()),
+                _ => s.len(),
+            };
+            let (line, col) = line_cache.run(pos);
+            format!("line {}, column {}: {}", line, col, e)
+        })
+        .collect()
+}
+
+// see `TranslateOptions::output_ratio_warn`
+fn output_ratio_warning(
+    opts: &TranslateOptions,
+    input_bytes: usize,
+    output_bytes: usize,
+) -> Option<String> {
+    let ratio = opts.output_ratio_warn?;
+    (output_bytes as f64 > ratio * input_bytes as f64).then(|| {
+        format!(
+            "generated output ({} bytes) exceeds {}x the input size ({} bytes)",
+            output_bytes, ratio, input_bytes
+        )
+    })
+}
+
+// a leading UTF-8 BOM (e.g. written by Windows editors) isn't part of the expression;
+// editors don't display it, so positions (incl. the source map) are relative to the rest
+fn strip_bom(s: &str) -> &str {
+    s.strip_prefix('\u{feff}').unwrap_or(s)
+}
+
+/// only parses the input, and returns the syntax errors (with positions)
+pub fn parse_errors(s: &str) -> Vec<String> {
+    let s = strip_bom(s);
+    render_parse_errors(s, rnix::parse(s).errors())
+}
+
 pub fn translate(s: &str, inp_name: &str) -> Result<(String, String), Vec<String>> {
+    translate_with_options(s, inp_name, &TranslateOptions::default()).map(|i| (i.js, i.map))
+}
+
+const ENTRY_JSDOC: &str = r#"/**
+ * @param {object} nixRt the runtime, providing `realise`, `export`, `import`, `readFile`
+ * @param {typeof import("nix-builtins")} nixBlti the exports of the `nix-builtins` package
+ * @returns {Promise<any>} the evaluated Nix value
+ */
+"#;
+
+/// the prefix must be usable as start of a JS identifier
+fn check_name_prefix(opts: &TranslateOptions) -> Result<(), Vec<String>> {
+    if is_js_ident(&opts.name_prefix) {
+        Ok(())
+    } else {
+        Err(vec![format!("invalid name prefix '{}'", opts.name_prefix)])
+    }
+}
+
+/// the name of the export must be usable as JS variable
+fn check_entry_export(opts: &TranslateOptions) -> Result<(), Vec<String>> {
+    match &opts.entry_export {
+        Some(name) if !is_js_ident(name) || JS_RESERVED.contains(&name.as_str()) => {
+            Err(vec![format!("invalid entry export name '{}'", name)])
+        }
+        _ => Ok(()),
+    }
+}
+
+fn web_worker_entry(name: &str) -> String {
+    format!(
+        "\nimport * as nixBlti from \"nix-builtins\";\n\
+         self.onmessage=async function(ev){{let reply;try{{const nixRt=ev.data||{{}};\
+         reply={{result:await nixBlti.initRtDep(nixRt).toJSON({}(nixRt,nixBlti))}};}}\
+         catch(e){{reply={{error:String(e)}};}}self.postMessage(reply);}};",
+        name
+    )
+}
+
+pub fn translate_with_options(
+    s: &str,
+    inp_name: &str,
+    opts: &TranslateOptions,
+) -> Result<TranslateOutput, Vec<String>> {
+    translate_impl(s, inp_name, opts, None)
+}
+
+/// identifiers referenced by an expression which aren't bound by it
+#[derive(Clone, Debug, Default)]
+pub struct FreeVars {
+    /// identifiers which are neither bound nor builtins
+    pub unbound: std::collections::BTreeSet<String>,
+    /// identifiers which might be provided by a `with` whose namespace isn't
+    /// statically known (and are unbound otherwise)
+    pub with_scoped: std::collections::BTreeSet<String>,
+}
+
+/// collects the free identifiers of an expression, i.e. the environment it needs
+/// (e.g. via `scopedImport`); parse errors are returned like by [`translate_with_options`]
+///
+/// ```
+/// let fv = nix2js::free_vars("{ a, ... }: with a; [ a foo bar (x: x) ]", &Default::default())
+///     .unwrap();
+/// assert!(fv.unbound.is_empty());
+/// assert_eq!(fv.with_scoped.into_iter().collect::<Vec<_>>(), ["bar", "foo"]);
+///
+/// let fv = nix2js::free_vars("let x = 1; in foo + x + builtins.length [ ]", &Default::default())
+///     .unwrap();
+/// assert_eq!(fv.unbound.into_iter().collect::<Vec<_>>(), ["foo"]);
+/// ```
+pub fn free_vars(s: &str, opts: &TranslateOptions) -> Result<FreeVars, Vec<String>> {
+    let opts = TranslateOptions {
+        no_source_map: true,
+        ..opts.clone()
+    };
+    let mut ret = FreeVars::default();
+    translate_impl(s, "", &opts, Some(&mut ret))?;
+    Ok(ret)
+}
+
+fn translate_impl(
+    s: &str,
+    inp_name: &str,
+    opts: &TranslateOptions,
+    free_vars: Option<&mut FreeVars>,
+) -> Result<TranslateOutput, Vec<String>> {
+    check_name_prefix(opts)?;
+    check_entry_export(opts)?;
+    let s = strip_bom(s);
     let parsed = rnix::parse(s);
 
+    // the parser only reports an "unexpected end of file" for these
+    if Root::cast(parsed.node()).and_then(|r| r.inner()).is_none() {
+        return Err(vec!["line 0: empty Nix expression".to_string()]);
+    }
+
     // return any occured parsing errors
-    {
-        let errs = parsed.errors();
-        if !errs.is_empty() {
-            return Err(errs.into_iter().map(|i| i.to_string()).collect());
-        }
+    let errs = render_parse_errors(s, parsed.errors());
+    if !errs.is_empty() && !opts.recover_parse_errors {
+        return Err(errs);
     }
 
     let (mut ret, mut names, mut mappings) = (
@@ -1052,42 +2089,506 @@ pub fn translate(s: &str, inp_name: &str) -> Result<(String, String), Vec<String
         Vec::new(),
         Vec::with_capacity((3 * s.len()) / 5),
     );
-    ret += "let ";
-    ret += NIX_OPERATORS;
-    ret += "=nixBlti.nixOp;let ";
-    ret += NIX_BUILTINS_RT;
-    ret += "=nixBlti.initRtDep(nixRt);let ";
-    ret += NIX_IN_SCOPE;
-    ret += "=nixBlti.mkScopeWith();return ";
-    match (Context {
+    // the source map offsets are tracked via `acc`,
+    // so the banner lines get accounted for automatically
+    if opts.ts_nocheck {
+        ret += "// @ts-nocheck\n/* eslint-disable */\n";
+    }
+    if let Some(banner) = &opts.banner {
+        for i in banner.lines() {
+            ret += "//";
+            if !i.is_empty() {
+                ret += " ";
+                ret += i;
+            }
+            ret += "\n";
+        }
+    }
+    let root_exports = match opts.entry_export {
+        Some(_) if opts.export_keys => root_exports(&parsed.node()),
+        _ => None,
+    };
+    let units = match &root_exports {
+        Some(xs) => xs
+            .iter()
+            .map(|(k, v)| (Some(k.as_str()), v.clone()))
+            .collect(),
+        None => vec![(opts.entry_export.as_deref(), parsed.node())],
+    };
+    let mut ctx = Context {
         line_cache: linetrack::LineCache::new(s),
         inp: s,
+        opts,
         acc: &mut ret,
         vars: DFL_VARS
             .iter()
-            .map(|(name, val)| (name.to_string(), *val))
+            .map(|(name, val)| (name.to_string(), val.clone()))
             .collect(),
-        with_stack: 0,
+        used_vars: Default::default(),
+        with_stack: Vec::new(),
+        tmp_count: 0,
+        flatten_let: false,
+        free_vars,
+        warnings: Vec::new(),
+        stats: Default::default(),
         names: &mut names,
         mappings: &mut mappings,
         lp_src: Default::default(),
         lp_dst: Default::default(),
+        lp_name: 0,
+    };
+    for (n, (export, node)) in units.into_iter().enumerate() {
+        if n != 0 {
+            ctx.push("\n");
+        }
+        if let Some(name) = export {
+            if opts.jsdoc {
+                ctx.push(ENTRY_JSDOC);
+            }
+            ctx.push(&format!(
+                "export const {}=async function(nixRt,nixBlti){{",
+                name
+            ));
+        }
+        if !opts.omit_preamble {
+            ctx.push(&runtime_preamble_prefixed(&opts.name_prefix));
+        }
+        if !opts.omit_preamble || export.is_some() {
+            ctx.push("return ");
+        }
+        if let Err(e) = ctx.translate_node(mksctx!(Nothing, Want), node) {
+            return Err(vec![e]);
+        }
+        if !opts.omit_preamble || export.is_some() {
+            ctx.push(";");
+        }
+        if export.is_some() {
+            ctx.push("}");
+        }
     }
-    .translate_node(mksctx!(Nothing, Want), parsed.node()))
-    {
-        Ok(()) => {}
-        Err(e) => return Err(vec![e]),
+    // the wrapper follows the translated code, so the source map is unaffected
+    if let (true, None, Some(name)) = (opts.web_worker, &root_exports, &opts.entry_export) {
+        ctx.push(&web_worker_entry(name));
+    }
+    let mut warnings = errs;
+    warnings.extend(ctx.warnings);
+    let mut stats = ctx.stats;
+    warnings.extend(output_ratio_warning(opts, s.len(), ret.len()));
+    stats.input_bytes = s.len();
+    stats.output_bytes = ret.len();
+    stats.distinct_names = names.len();
+    if opts.no_source_map {
+        return Ok(TranslateOutput {
+            js: ret,
+            map: String::new(),
+            warnings,
+            stats,
+        });
     }
-    ret += ";";
     let mappings = String::from_utf8(mappings).unwrap();
-    Ok((
-        ret,
-        serde_json::json!({
-            "version": 3,
-            "sources": [inp_name.to_string()],
-            "names": names,
-            "mappings": mappings,
-        })
-        .to_string(),
-    ))
+    let mut map = serde_json::json!({
+        "version": 3,
+        "sources": [inp_name.to_string()],
+        "names": names,
+        "mappings": mappings,
+    });
+    if let Some(source_root) = &opts.source_root {
+        map["sourceRoot"] = source_root.clone().into();
+    }
+    if opts.generator_marker {
+        map["x_generator"] = concat!("nix2js ", env!("CARGO_PKG_VERSION")).into();
+    }
+    Ok(TranslateOutput {
+        js: ret,
+        map: map.to_string(),
+        warnings,
+        stats,
+    })
+}
+
+/// like [`translate_with_options`], but for byte-oriented callers
+/// (e.g. memory-mapped files); the input must be valid UTF-8.
+pub fn translate_bytes(
+    s: &[u8],
+    inp_name: &str,
+    opts: &TranslateOptions,
+) -> Result<(Vec<u8>, Vec<u8>), Vec<String>> {
+    let s = std::str::from_utf8(s).map_err(|e| vec![format!("{}: {}", inp_name, e)])?;
+    translate_with_options(s, inp_name, opts).map(|i| (i.js.into_bytes(), i.map.into_bytes()))
+}
+
+/// appends the source map to the generated code as a base64 data URI
+/// (`//# sourceMappingURL=data:...`), unless it is disabled
+pub fn inline_source_map(out: &mut TranslateOutput, opts: &TranslateOptions) {
+    if !opts.no_source_map {
+        out.js += "\n//# sourceMappingURL=data:application/json;charset=utf-8;base64,";
+        out.js += &base64::encode(&out.map);
+    }
+}
+
+/// like [`translate_with_options`], but with an inline source map (see [`inline_source_map`])
+pub fn translate_inline(
+    s: &str,
+    inp_name: &str,
+    opts: &TranslateOptions,
+) -> Result<TranslateOutput, Vec<String>> {
+    let mut ret = translate_with_options(s, inp_name, opts)?;
+    inline_source_map(&mut ret, opts);
+    Ok(ret)
+}
+
+// the name of `path` in the source map
+fn source_name(path: &std::path::Path, opts: &TranslateOptions) -> String {
+    let path: std::path::PathBuf = path
+        .components()
+        .filter(|i| !matches!(i, std::path::Component::CurDir))
+        .collect();
+    let rel = opts
+        .source_root
+        .as_deref()
+        .and_then(|root| path.strip_prefix(root).ok());
+    rel.unwrap_or(&path).to_string_lossy().into_owned()
+}
+
+/// reads and translates the file at `path`; its name in the source map is the path
+/// without `.` components, relative to `TranslateOptions::source_root` if located inside of it
+pub fn translate_file(
+    path: &std::path::Path,
+    opts: &TranslateOptions,
+) -> Result<TranslateOutput, Vec<String>> {
+    let s =
+        std::fs::read_to_string(path).map_err(|e| vec![format!("{}: {}", path.display(), e)])?;
+    translate_with_options(&s, &source_name(path, opts), opts)
+}
+
+/// dumps the parsed syntax tree (including parsing errors) as JSON,
+/// each node or token with its kind and text range; meant as a diagnostic aid.
+#[cfg(feature = "debug-tools")]
+pub fn dump_ast(s: &str) -> String {
+    use rnix::NodeOrToken;
+    use serde_json::{json, Value};
+
+    fn walk(el: rnix::SyntaxElement) -> Value {
+        let range = el.text_range();
+        let range = [u32::from(range.start()), u32::from(range.end())];
+        match el {
+            NodeOrToken::Node(n) => json!({
+                "kind": format!("{:?}", n.kind()),
+                "range": range,
+                "children": n.children_with_tokens().map(walk).collect::<Vec<_>>(),
+            }),
+            NodeOrToken::Token(t) => json!({
+                "kind": format!("{:?}", t.kind()),
+                "range": range,
+                "text": t.text().to_string(),
+            }),
+        }
+    }
+
+    let parsed = rnix::parse(s);
+    json!({
+        "errors": parsed.errors().into_iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+        "root": walk(NodeOrToken::Node(parsed.node())),
+    })
+    .to_string()
+}
+
+/// translates multiple files into a single bundle, which shares the runtime preamble
+/// (`nixOp`, `nixBltiRT`, `nixInScope`, see [`runtime_preamble_prefixed`]) instead of
+/// repeating it for every file. Like the output of [`translate`], the bundle is the
+/// body of a function expecting `nixRt` and `nixBlti`; it returns an object which maps
+/// the names of the inputs to `async function(){...}`s evaluating the files.
+///
+/// All files share the same `nixRt` (which thus can't depend on the importing file,
+/// e.g. `import` must handle relative paths itself) and the same (read-only) root scope.
+/// The warnings are prefixed with the input names; the source map lists every input
+/// in `sources` (and `sourcesContent`); the banner and the ES module options are ignored.
+///
+/// ```
+/// let out = nix2js::translate_bundle(
+///     &[("a.nix", "1 + 1"), ("b.nix", "builtins.length [ 1 2 ]")],
+///     &Default::default(),
+/// )
+/// .unwrap();
+/// assert_eq!(out.js.matches("nixBlti.initRtDep(nixRt)").count(), 1);
+/// assert!(out.js.contains("\"a.nix\":async function(){return "));
+/// assert!(out.js.contains("\"b.nix\":async function(){return "));
+///
+/// let map: serde_json::Value = serde_json::from_str(&out.map).unwrap();
+/// assert_eq!(map["sources"], serde_json::json!(["a.nix", "b.nix"]));
+/// assert_eq!(map["sourcesContent"][1], "builtins.length [ 1 2 ]");
+/// // the second file starts on the second line of the bundle
+/// assert_eq!(map["mappings"].as_str().unwrap().split(';').count(), 2);
+/// ```
+pub fn translate_bundle(
+    inputs: &[(&str, &str)],
+    opts: &TranslateOptions,
+) -> Result<TranslateOutput, Vec<String>> {
+    check_name_prefix(opts)?;
+    let inner_opts = TranslateOptions {
+        omit_preamble: true,
+        source_root: None,
+        generator_marker: false,
+        banner: None,
+        ts_nocheck: false,
+        entry_export: None,
+        export_keys: false,
+        web_worker: false,
+        ..opts.clone()
+    };
+    let mut ret = String::new();
+    if opts.ts_nocheck {
+        ret += "// @ts-nocheck\n/* eslint-disable */\n";
+    }
+    ret += &runtime_preamble_prefixed(&opts.name_prefix);
+    ret += "return {";
+    let (mut warnings, mut errs) = (Vec::new(), Vec::new());
+    let mut stats = TranslateStats::default();
+    let (mut sources, mut contents, mut names, mut segments) =
+        (Vec::new(), Vec::new(), Vec::<String>::new(), Vec::new());
+    for (n, (name, s)) in inputs.iter().enumerate() {
+        match translate_with_options(s, name, &inner_opts) {
+            Ok(out) => {
+                if n != 0 {
+                    ret += ",\n";
+                }
+                ret += &escape_str(name);
+                ret += ":async function(){return ";
+                if !opts.no_source_map {
+                    // shift the segments of the file to its position inside of the bundle
+                    let line = ret.matches('\n').count();
+                    let col = ret.len() - ret.rfind('\n').map_or(0, |i| i + 1);
+                    let map: serde_json::Value = serde_json::from_str(&out.map).unwrap();
+                    let name_base = names.len();
+                    names.extend(
+                        map["names"]
+                            .as_array()
+                            .unwrap()
+                            .iter()
+                            .map(|i| i.as_str().unwrap().to_string()),
+                    );
+                    for mut seg in decode_mappings(map["mappings"].as_str().unwrap()) {
+                        if seg.gen_line == 0 {
+                            seg.gen_col += col;
+                        }
+                        seg.gen_line += line;
+                        if let Some(src) = &mut seg.src {
+                            src.0 = sources.len();
+                            src.3 = src.3.map(|i| i + name_base);
+                        }
+                        segments.push(seg);
+                    }
+                    sources.push(name.to_string());
+                    contents.push(strip_bom(s).to_string());
+                }
+                ret += &out.js;
+                ret += ";}";
+                warnings.extend(out.warnings.into_iter().map(|w| format!("{}: {}", name, w)));
+                stats.input_bytes += out.stats.input_bytes;
+                stats.node_count += out.stats.node_count;
+                stats.fast_path_attrsets += out.stats.fast_path_attrsets;
+                stats.async_wraps += out.stats.async_wraps;
+            }
+            Err(xs) => errs.extend(xs.into_iter().map(|e| format!("{}: {}", name, e))),
+        }
+    }
+    ret += "};";
+    if !errs.is_empty() {
+        return Err(errs);
+    }
+    stats.output_bytes = ret.len();
+    stats.distinct_names = names.len();
+    stats.map_segments = segments.len();
+    if opts.no_source_map {
+        return Ok(TranslateOutput {
+            js: ret,
+            map: String::new(),
+            warnings,
+            stats,
+        });
+    }
+    let mut map = serde_json::json!({
+        "version": 3,
+        "sources": sources,
+        "sourcesContent": contents,
+        "names": names,
+        "mappings": encode_mappings(&segments),
+    });
+    if let Some(source_root) = &opts.source_root {
+        map["sourceRoot"] = source_root.clone().into();
+    }
+    if opts.generator_marker {
+        map["x_generator"] = concat!("nix2js ", env!("CARGO_PKG_VERSION")).into();
+    }
+    Ok(TranslateOutput {
+        js: ret,
+        map: map.to_string(),
+        warnings,
+        stats,
+    })
+}
+
+/// like [`translate_bytes`], but replaces invalid UTF-8 sequences with U+FFFD
+/// instead of failing (the first one gets reported as warning);
+/// source map positions refer to the sanitized input.
+pub fn translate_lossy(
+    s: &[u8],
+    inp_name: &str,
+    opts: &TranslateOptions,
+) -> Result<TranslateOutput, Vec<String>> {
+    let invalid = std::str::from_utf8(s).err();
+    let mut ret = translate_with_options(&String::from_utf8_lossy(s), inp_name, opts)?;
+    if let Some(e) = invalid {
+        let lineno = s[..e.valid_up_to()].iter().filter(|&&c| c == b'\n').count();
+        ret.warnings.insert(
+            0,
+            format!(
+                "line {}: input contains invalid UTF-8 ({}), replaced with U+FFFD",
+                lineno, e
+            ),
+        );
+    }
+    Ok(ret)
+}
+
+/// an element of the flattened syntax tree, see `structure`
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+enum StructElem {
+    Node(rnix::SyntaxKind),
+    // marks the end of the children, otherwise the nesting would be ambiguous
+    End,
+    Token(rnix::SyntaxKind, String),
+    // line and condition text of an `assert`, which get embedded into the generated code
+    Assert(usize, String),
+}
+
+/// the syntax tree of `s` without trivia (whitespace and comments),
+/// so that merely reformatted inputs compare equal; `None` if `s` doesn't parse
+fn structure(s: &str) -> Option<Vec<StructElem>> {
+    use rnix::NodeOrToken;
+
+    fn walk(s: &str, el: rnix::SyntaxElement, ret: &mut Vec<StructElem>) {
+        match el {
+            NodeOrToken::Node(n) => {
+                ret.push(StructElem::Node(n.kind()));
+                if let Some(art) = Assert::cast(n.clone()) {
+                    ret.push(StructElem::Assert(
+                        s[..usize::from(n.text_range().start())]
+                            .matches('\n')
+                            .count(),
+                        art.condition()
+                            .map(|i| i.text().to_string())
+                            .unwrap_or_default(),
+                    ));
+                }
+                for i in n.children_with_tokens() {
+                    walk(s, i, ret);
+                }
+                ret.push(StructElem::End);
+            }
+            NodeOrToken::Token(t) if !t.kind().is_trivia() => {
+                ret.push(StructElem::Token(t.kind(), t.text().to_string()));
+            }
+            NodeOrToken::Token(_) => {}
+        }
+    }
+
+    let parsed = rnix::parse(s);
+    if !parsed.errors().is_empty() {
+        return None;
+    }
+    let mut ret = Vec::new();
+    walk(s, NodeOrToken::Node(parsed.node()), &mut ret);
+    Some(ret)
+}
+
+/// hash of the syntax tree of `s` which ignores trivia (whitespace and comments),
+/// so that merely reformatted inputs hash the same; `None` if `s` doesn't parse.
+/// The line and text of `assert` conditions are included, because they are
+/// embedded into the generated code.
+pub fn structural_hash(s: &str) -> Option<u64> {
+    use std::hash::{Hash, Hasher};
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    structure(s)?.hash(&mut h);
+    Some(h.finish())
+}
+
+/// caches translations keyed on the syntax tree of the input (like [`structural_hash`],
+/// but entries are compared in full, so hash collisions can't return another input's
+/// translation); reformatted-but-equivalent inputs hit the cache.
+///
+/// The generated code only depends on positions via `assert` messages (which contain
+/// the line and the condition text), these are part of the key. The source map
+/// depends on all positions, thus the cache is only consulted if
+/// `TranslateOptions::no_source_map` is set; otherwise every call translates the
+/// input (and refreshes the cache entry). Translations with warnings don't get
+/// cached, as those contain line numbers; except for the one about
+/// `TranslateOptions::output_ratio_warn`, which gets recomputed on a hit
+/// (like `TranslateStats::input_bytes`).
+///
+/// ```
+/// let mut cache = nix2js::TranslateCache::new(nix2js::TranslateOptions {
+///     no_source_map: true,
+///     ..Default::default()
+/// });
+/// let a = cache.translate("{ a = 1; }", "a.nix").unwrap();
+/// let b = cache.translate("{\n  a = 1; # one\n}\n", "b.nix").unwrap();
+/// assert_eq!(a.js, b.js);
+/// assert_eq!(cache.hits(), 1);
+/// assert!(cache.translate("{ a = 2; }", "c.nix").unwrap().js != a.js);
+/// assert_eq!(cache.hits(), 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TranslateCache {
+    opts: TranslateOptions,
+    entries: std::collections::HashMap<Vec<StructElem>, TranslateOutput>,
+    hits: usize,
+}
+
+impl TranslateCache {
+    pub fn new(opts: TranslateOptions) -> Self {
+        Self {
+            opts,
+            ..Default::default()
+        }
+    }
+
+    pub fn options(&self) -> &TranslateOptions {
+        &self.opts
+    }
+
+    /// number of translations answered from the cache
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// like [`translate_with_options`], using the options of the cache
+    pub fn translate(&mut self, s: &str, inp_name: &str) -> Result<TranslateOutput, Vec<String>> {
+        let input_bytes = strip_bom(s).len();
+        let key = structure(strip_bom(s));
+        if self.opts.no_source_map {
+            if let Some(ret) = key.as_ref().and_then(|k| self.entries.get(k)) {
+                self.hits += 1;
+                let mut ret = ret.clone();
+                ret.stats.input_bytes = input_bytes;
+                ret.warnings
+                    .extend(output_ratio_warning(&self.opts, input_bytes, ret.js.len()));
+                return Ok(ret);
+            }
+        }
+        let ret = translate_with_options(s, inp_name, &self.opts)?;
+        if let Some(k) = key {
+            let ratio_warning = output_ratio_warning(&self.opts, input_bytes, ret.js.len());
+            if ret
+                .warnings
+                .iter()
+                .all(|w| Some(w) == ratio_warning.as_ref())
+            {
+                let mut entry = ret.clone();
+                entry.warnings.clear();
+                self.entries.insert(k, entry);
+            }
+        }
+        Ok(ret)
+    }
 }
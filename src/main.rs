@@ -1,37 +1,466 @@
 use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
 
-fn main() -> io::Result<()> {
-    let mut args: Vec<_> = std::env::args().skip(1).collect();
+const USAGE: &str = "USAGE: nix2js [OPTIONS] [INPUT_FILE [OUTPUT_FILE [OUT_SOURCE_MAP_FILE]]]
+       nix2js [OPTIONS] [--output-extension EXT] [--jobs N] INPUT_DIR OUTPUT_DIR
+       nix2js lint INPUT_FILE...
+       nix2js --list-builtins [--json]
 
-    if args.is_empty() {
-        let mut inp = String::new();
-        io::stdin().lock().read_to_string(&mut inp)?;
-        match nix2js::translate(&inp, "<stdin>") {
-            Ok((x, _)) => {
-                io::stdout().write_all(x.as_bytes())?;
+OPTIONS:
+  --source-root ROOT   set the `sourceRoot` of the source map
+  --banner TEXT        emit TEXT as leading comment
+  --banner-file FILE   emit the contents of FILE as leading comment
+  --ts-nocheck         start the output with `// @ts-nocheck` and `/* eslint-disable */`
+  --no-source-map      don't generate source maps
+  --map-inline         embed the source map as base64 data URI into the output,
+                       instead of writing a separate map file
+  --strip-preamble     emit only the translated expression, without the runtime
+                       bindings (`nixOp`, `nixBltiRT`, `nixInScope`) and `return`
+  --verbose            print translation statistics
+  --parse-only         only check the syntax of the input, don't emit anything
+  --check-idents       list the free identifiers of the input (`unbound: NAME`),
+                       and those which might come from a `with` (`with: NAME`)
+  --recover            translate inputs with syntax errors as far as possible,
+                       the errors are reported as warnings
+  --entry-export NAME  emit an ES module with the named export
+                       `export const NAME = async function(nixRt, nixBlti) { ... }`
+  --export-keys        with --entry-export: export every key of a top-level attrset
+                       separately, to allow tree-shaking (experimental)
+  --web-worker         with --entry-export: append a Web Worker entry point which
+                       evaluates the export on `postMessage` and replies with the
+                       result serialized via `builtins.toJSON`
+  --name-prefix PFX    use PFX instead of `nix` as prefix of the generated identifiers
+  --minify             shorten the generated code (whitespace, temporaries)
+  --jsdoc              document the parameters of the `--entry-export` function
+                       via a JSDoc comment
+  --path-mode MODE     how to emit paths: `store` (default), `string`,
+                       or `custom:METHOD` to call `nixRt.METHOD(anchor, path)`
+  --trace-mode MODE    how to emit `builtins.trace`: `runtime` (default, calls
+                       `nixRt.trace(msg)`), or `suppress` to skip the messages
+
+ENVIRONMENT:
+  NIX2JS_TRACE         if set (and non-empty), log every translated node to stderr";
+
+fn list_builtins(json: bool) {
+    let xs = nix2js::list_builtins();
+    if json {
+        let xs: Vec<_> = xs
+            .iter()
+            .map(|i| {
+                serde_json::json!({
+                    "name": i.name,
+                    "global": i.global,
+                    "impure": i.impure,
+                    "folded": i.folded,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::Value::from(xs));
+        return;
+    }
+    for i in xs {
+        let mut line = i.name.to_string();
+        for (flag, desc) in [
+            (i.global, "global"),
+            (i.impure, "impure"),
+            (i.folded, "folded"),
+        ] {
+            if flag {
+                line += " ";
+                line += desc;
+            }
+        }
+        println!("{}", line);
+    }
+}
+
+fn print_errors(xs: Vec<String>) {
+    for e in xs {
+        eprintln!("{}", e);
+    }
+}
+
+fn translate_file(
+    path: &Path,
+    map_inline: bool,
+    opts: &nix2js::TranslateOptions,
+) -> Result<nix2js::TranslateOutput, Vec<String>> {
+    let mut ret = nix2js::translate_file(path, opts)?;
+    if map_inline {
+        nix2js::inline_source_map(&mut ret, opts);
+    }
+    Ok(ret)
+}
+
+/// only reports diagnostics, returns if any errors were encountered
+fn lint(files: Vec<String>, opts: &nix2js::TranslateOptions) -> io::Result<bool> {
+    let mut failed = false;
+    for inpf in files {
+        match nix2js::translate_file(Path::new(&inpf), opts) {
+            Ok(x) => {
+                for w in x.warnings {
+                    eprintln!("{}: {}", inpf, w);
+                }
             }
             Err(xs) => {
+                failed = true;
                 for e in xs {
-                    eprintln!("{}", e);
+                    eprintln!("{}: error: {}", inpf, e);
                 }
             }
         }
-    } else {
-        let inpf = args.remove(0);
-        if inpf == "--help" {
-            println!("USAGE: nix2js [INPUT_FILE [OUTPUT_FILE [OUT_SOURCE_MAP_FILE]]]");
-            return Ok(());
+    }
+    Ok(failed)
+}
+
+/// collects all `.nix` files in `inpd` (recursively), paired with their output file
+/// in `outd` (`foo.nix` becomes `foo{ext}`), and creates the output directories
+fn collect_dir(
+    inpd: &Path,
+    outd: &Path,
+    ext: &str,
+    files: &mut Vec<(PathBuf, PathBuf)>,
+) -> io::Result<()> {
+    std::fs::create_dir_all(outd)?;
+    let mut ents = std::fs::read_dir(inpd)?.collect::<io::Result<Vec<_>>>()?;
+    ents.sort_unstable_by_key(|i| i.file_name());
+    for ent in ents {
+        let inpf = ent.path();
+        if ent.file_type()?.is_dir() {
+            collect_dir(&inpf, &outd.join(ent.file_name()), ext, files)?;
+        } else if let Some(stem) = ent
+            .file_name()
+            .to_str()
+            .and_then(|i| i.strip_suffix(".nix"))
+        {
+            files.push((inpf, outd.join(format!("{}{}", stem, ext))));
         }
-        let inp = std::fs::read_to_string(&inpf)?;
-        match nix2js::translate(&inp, &inpf) {
-            Err(xs) => {
-                for e in xs {
-                    eprintln!("{}", e);
+    }
+    Ok(())
+}
+
+/// transpiles a single file of a directory, accompanied by the source map `{outf}.map`
+/// (unless it gets inlined); returns the diagnostics and if any errors were encountered
+fn translate_dir_file(
+    inpf: &Path,
+    outf: &Path,
+    verbose: bool,
+    map_inline: bool,
+    opts: &nix2js::TranslateOptions,
+) -> io::Result<(Vec<String>, bool)> {
+    let inpfs = inpf.to_string_lossy();
+    let mut diags = Vec::new();
+    match translate_file(inpf, map_inline, opts) {
+        Ok(nix2js::TranslateOutput {
+            js,
+            map,
+            warnings,
+            stats,
+        }) => {
+            for w in warnings {
+                diags.push(format!("{}: {}", inpfs, w));
+            }
+            if verbose {
+                diags.push(format!("{}: {:?}", inpfs, stats));
+            }
+            if opts.no_source_map || map_inline {
+                std::fs::write(outf, js.as_bytes())?;
+                return Ok((diags, false));
+            }
+            let mut mapf = outf.as_os_str().to_owned();
+            mapf.push(".map");
+            let mapf = PathBuf::from(mapf);
+            std::fs::write(&mapf, map.as_bytes())?;
+            std::fs::write(
+                outf,
+                format!(
+                    "{}\n//# sourceMappingURL={}",
+                    js,
+                    mapf.file_name().unwrap().to_string_lossy()
+                )
+                .as_bytes(),
+            )?;
+            Ok((diags, false))
+        }
+        Err(xs) => {
+            for e in xs {
+                diags.push(format!("{}: error: {}", inpfs, e));
+            }
+            Ok((diags, true))
+        }
+    }
+}
+
+/// transpiles all `.nix` files in `inpd` (recursively) into `outd`, using `jobs` threads;
+/// the diagnostics are printed in the order of the (sorted) paths, independent of `jobs`.
+/// returns if any errors were encountered
+fn translate_dir(
+    inpd: &Path,
+    outd: &Path,
+    ext: &str,
+    verbose: bool,
+    map_inline: bool,
+    jobs: usize,
+    opts: &nix2js::TranslateOptions,
+) -> io::Result<bool> {
+    let mut files = Vec::new();
+    collect_dir(inpd, outd, ext, &mut files)?;
+    let next = AtomicUsize::new(0);
+    let results: Vec<_> = files.iter().map(|_| Mutex::new(None)).collect();
+    std::thread::scope(|s| {
+        for _ in 0..jobs.min(files.len()) {
+            s.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let (inpf, outf) = match files.get(idx) {
+                    Some(x) => x,
+                    None => break,
+                };
+                let res = translate_dir_file(inpf, outf, verbose, map_inline, opts);
+                *results[idx].lock().unwrap() = Some(res);
+            });
+        }
+    });
+    let mut failed = false;
+    for res in results {
+        let (diags, file_failed) = res.into_inner().unwrap().unwrap()?;
+        for d in diags {
+            eprintln!("{}", d);
+        }
+        failed |= file_failed;
+    }
+    Ok(failed)
+}
+
+fn main() -> io::Result<()> {
+    let mut opts = nix2js::TranslateOptions {
+        trace_codegen: std::env::var_os("NIX2JS_TRACE").is_some_and(|i| !i.is_empty()),
+        ..Default::default()
+    };
+    let mut ext = ".nix.js".to_string();
+    let mut verbose = false;
+    let mut parse_only = false;
+    let mut check_idents = false;
+    let mut map_inline = false;
+    let mut jobs = 1;
+    let mut list_builtins_mode = false;
+    let mut json = false;
+    let mut args = Vec::new();
+    let mut argsit = std::env::args().skip(1);
+    while let Some(arg) = argsit.next() {
+        match arg.as_str() {
+            "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            "--list-builtins" => list_builtins_mode = true,
+            "--json" => json = true,
+            "--source-root" => {
+                opts.source_root = Some(argsit.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "--source-root: missing argument",
+                    )
+                })?);
+            }
+            "--banner" | "--banner-file" => {
+                let val = argsit.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{}: missing argument", arg),
+                    )
+                })?;
+                opts.banner = Some(if arg == "--banner-file" {
+                    std::fs::read_to_string(val)?
+                } else {
+                    val
+                });
+            }
+            "--no-source-map" => opts.no_source_map = true,
+            "--ts-nocheck" => opts.ts_nocheck = true,
+            "--map-inline" => map_inline = true,
+            "--jsdoc" => opts.jsdoc = true,
+            "--export-keys" => opts.export_keys = true,
+            "--web-worker" => opts.web_worker = true,
+            "--minify" => opts.minify = true,
+            "--name-prefix" => {
+                opts.name_prefix = argsit.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "--name-prefix: missing argument",
+                    )
+                })?;
+            }
+            "--strip-preamble" => opts.omit_preamble = true,
+            "--verbose" => verbose = true,
+            "--parse-only" => parse_only = true,
+            "--check-idents" => check_idents = true,
+            "--recover" => opts.recover_parse_errors = true,
+            "--trace-mode" => {
+                let val = argsit.next().unwrap_or_default();
+                opts.trace_mode = match val.as_str() {
+                    "runtime" => nix2js::TraceMode::Runtime,
+                    "suppress" => nix2js::TraceMode::Suppress,
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("--trace-mode: invalid mode '{}'", val),
+                        ))
+                    }
+                };
+            }
+            "--path-mode" => {
+                let val = argsit.next().unwrap_or_default();
+                opts.path_mode = match val.as_str() {
+                    "store" => nix2js::PathMode::Store,
+                    "string" => nix2js::PathMode::String,
+                    _ => match val.strip_prefix("custom:") {
+                        Some(method) if !method.is_empty() => {
+                            nix2js::PathMode::Custom(method.to_string())
+                        }
+                        _ => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                format!("--path-mode: invalid mode '{}'", val),
+                            ))
+                        }
+                    },
+                };
+            }
+            "--entry-export" => {
+                opts.entry_export = Some(argsit.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "--entry-export: missing argument",
+                    )
+                })?);
+            }
+            "--jobs" => {
+                jobs = argsit
+                    .next()
+                    .and_then(|i| i.parse::<usize>().ok())
+                    .filter(|&i| i > 0)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "--jobs: expected a positive number",
+                        )
+                    })?;
+            }
+            "--output-extension" => {
+                ext = argsit.next().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "--output-extension: missing argument",
+                    )
+                })?;
+            }
+            _ => args.push(arg),
+        }
+    }
+    if list_builtins_mode {
+        list_builtins(json);
+        return Ok(());
+    }
+    if opts.export_keys && opts.entry_export.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--export-keys: requires --entry-export",
+        ));
+    }
+    if opts.web_worker && (opts.entry_export.is_none() || opts.export_keys) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--web-worker: requires --entry-export, and is incompatible with --export-keys",
+        ));
+    }
+
+    if parse_only || check_idents {
+        let (inp, inpf) = match args.first() {
+            Some(inpf) => (std::fs::read_to_string(inpf)?, inpf.as_str()),
+            None => {
+                let mut inp = String::new();
+                io::stdin().lock().read_to_string(&mut inp)?;
+                (inp, "<stdin>")
+            }
+        };
+        let errs = if parse_only {
+            nix2js::parse_errors(&inp)
+        } else {
+            match nix2js::free_vars(&inp, &opts) {
+                Ok(fv) => {
+                    for i in &fv.unbound {
+                        println!("unbound: {}", i);
+                    }
+                    for i in &fv.with_scoped {
+                        println!("with: {}", i);
+                    }
+                    Vec::new()
                 }
+                Err(errs) => errs,
+            }
+        };
+        for e in &errs {
+            eprintln!("{}: error: {}", inpf, e);
+        }
+        if !errs.is_empty() {
+            std::process::exit(1);
+        }
+    } else if args.first().map(|i| i.as_str()) == Some("lint") {
+        args.remove(0);
+        if lint(args, &opts)? {
+            std::process::exit(1);
+        }
+    } else if args.len() == 2 && Path::new(&args[0]).is_dir() {
+        if translate_dir(
+            Path::new(&args[0]),
+            Path::new(&args[1]),
+            &ext,
+            verbose,
+            map_inline,
+            jobs,
+            &opts,
+        )? {
+            std::process::exit(1);
+        }
+    } else if args.is_empty() {
+        let mut inp = String::new();
+        io::stdin().lock().read_to_string(&mut inp)?;
+        let res = if map_inline {
+            nix2js::translate_inline(&inp, "<stdin>", &opts)
+        } else {
+            nix2js::translate_with_options(&inp, "<stdin>", &opts)
+        };
+        match res {
+            Ok(x) => {
+                print_errors(x.warnings);
+                io::stdout().write_all(x.js.as_bytes())?;
             }
-            Ok((mut js, map)) => {
+            Err(xs) => print_errors(xs),
+        }
+    } else {
+        let inpf = args.remove(0);
+        match translate_file(Path::new(&inpf), map_inline, &opts) {
+            Err(xs) => print_errors(xs),
+            Ok(nix2js::TranslateOutput {
+                mut js,
+                map,
+                warnings,
+                stats,
+            }) => {
+                print_errors(warnings);
+                if verbose {
+                    eprintln!("{}: {:?}", inpf, stats);
+                }
                 if let Some(outpf) = args.get(0) {
-                    if let Some(mapf) = args.get(1) {
+                    if let Some(mapf) = args.get(1).filter(|_| opts.no_source_map) {
+                        eprintln!("--no-source-map given, not writing {}", mapf);
+                    } else if let Some(mapf) = args.get(1).filter(|_| map_inline) {
+                        eprintln!("--map-inline given, not writing {}", mapf);
+                    } else if let Some(mapf) = args.get(1) {
                         std::fs::write(&mapf, map.as_bytes())?;
                         js += "\n//# sourceMappingURL=";
                         js += mapf;
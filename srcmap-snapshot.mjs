@@ -0,0 +1,96 @@
+// snapshot tests for the generated source maps:
+// translates `docs/srcmaps/*.nix`, decodes the `mappings`, and compares them
+// against the committed tables in `docs/srcmaps/*.nix.mappings`.
+// USAGE: node srcmap-snapshot.mjs [--update]
+import fs from 'node:fs/promises';
+import path from 'node:path';
+import { fileURLToPath } from 'node:url';
+import { translate } from 'nix2js-wasm';
+
+const B64 = 'ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/';
+
+function decodeVlq(s) {
+    let ret = [];
+    let cur = 0, shift = 0;
+    for (const c of s) {
+        const digit = B64.indexOf(c);
+        if (digit < 0) {
+            throw new Error('invalid base64 digit in mappings: ' + c);
+        }
+        cur += (digit & 31) << shift;
+        if (digit & 32) {
+            shift += 5;
+        } else {
+            ret.push((cur & 1) ? -(cur >> 1) : (cur >> 1));
+            cur = shift = 0;
+        }
+    }
+    return ret;
+}
+
+// returns `[genLine, genCol, srcLine, srcCol, nameIdx]` tuples,
+// the last three are `undefined` for unmapped segments.
+export function decodeMappings(mappings) {
+    let ret = [];
+    let srcLine = 0, srcCol = 0, nameIdx = 0;
+    mappings.split(';').forEach((line, genLine) => {
+        let genCol = 0;
+        for (const seg of line.split(',').filter(x => x)) {
+            const f = decodeVlq(seg);
+            genCol += f[0];
+            if (f.length < 4) {
+                ret.push([genLine, genCol]);
+                continue;
+            }
+            // there is only a single source, thus f[1] is always 0
+            srcLine += f[2];
+            srcCol += f[3];
+            if (f.length >= 5) {
+                nameIdx += f[4];
+                ret.push([genLine, genCol, srcLine, srcCol, nameIdx]);
+            } else {
+                ret.push([genLine, genCol, srcLine, srcCol]);
+            }
+        }
+    });
+    return ret;
+}
+
+function fmtTable(map) {
+    return decodeMappings(map.mappings).map(([gl, gc, sl, sc, ni]) => {
+        let ret = gl + ':' + gc;
+        if (sl !== undefined) {
+            ret += ' -> ' + sl + ':' + sc;
+        }
+        if (ni !== undefined) {
+            ret += ' ' + (ni < map.names.length ? map.names[ni] : '#' + ni);
+        }
+        return ret + '\n';
+    }).join('');
+}
+
+const update = process.argv.includes('--update');
+const dir = path.resolve(path.dirname(fileURLToPath(import.meta.url)), 'docs/srcmaps');
+let failed = false;
+for (const i of (await fs.readdir(dir)).filter(x => x.endsWith('.nix')).sort()) {
+    const [, map] = translate(await fs.readFile(path.join(dir, i), 'utf8'), i);
+    const table = fmtTable(JSON.parse(map));
+    const tablef = path.join(dir, i + '.mappings');
+    if (update) {
+        await fs.writeFile(tablef, table);
+        continue;
+    }
+    let expected = null;
+    try {
+        expected = await fs.readFile(tablef, 'utf8');
+    } catch (e) { }
+    if (table === expected) {
+        console.log(i + ': ok');
+    } else {
+        failed = true;
+        console.log(i + ': MISMATCH, got:\n' + table);
+    }
+}
+if (failed) {
+    process.exitCode = 1;
+}
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// a fresh, empty scratch directory for the test `name`
+fn tmpdir(name: &str) -> PathBuf {
+    let ret = std::env::temp_dir().join(format!("nix2js-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&ret);
+    std::fs::create_dir_all(&ret).unwrap();
+    ret
+}
+
+fn nix2js(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nix2js"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// the contents of all files below `dir`, by their relative path
+fn read_tree(dir: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+    let mut ret = BTreeMap::new();
+    let mut todo = vec![dir.to_path_buf()];
+    while let Some(d) = todo.pop() {
+        for ent in std::fs::read_dir(d).unwrap() {
+            let path = ent.unwrap().path();
+            if path.is_dir() {
+                todo.push(path);
+            } else {
+                let content = std::fs::read(&path).unwrap();
+                ret.insert(path.strip_prefix(dir).unwrap().to_path_buf(), content);
+            }
+        }
+    }
+    ret
+}
+
+/// copies the fixtures into `dir`, spread over a few subdirectories
+fn write_fixtures(dir: &Path) {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/testset");
+    for (i, ent) in std::fs::read_dir(fixtures).unwrap().enumerate() {
+        let path = ent.unwrap().path();
+        if path.extension().is_some_and(|e| e == "nix") {
+            let subdir = dir.join(format!("d{}", i % 3));
+            std::fs::create_dir_all(&subdir).unwrap();
+            std::fs::copy(&path, subdir.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+}
+
+#[test]
+fn source_root() {
+    let dir = tmpdir("source-root");
+    let inpf = dir.join("src/a.nix");
+    std::fs::create_dir(dir.join("src")).unwrap();
+    std::fs::write(&inpf, "let a = 1; in a").unwrap();
+    let (outf, mapf) = (dir.join("a.js"), dir.join("a.js.map"));
+    let root = dir.join("src");
+    let run = nix2js(&["--source-root".as_ref(), &root, &inpf, &outf, &mapf]);
+    assert!(run.status.success());
+    let map: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&mapf).unwrap()).unwrap();
+    assert_eq!(map["sourceRoot"], root.to_str().unwrap());
+    assert_eq!(map["sources"], serde_json::json!(["a.nix"]));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn jobs() {
+    let dir = tmpdir("jobs");
+    let inpd = dir.join("in");
+    write_fixtures(&inpd);
+    // a warning per file, to check the order of the diagnostics
+    std::fs::write(inpd.join("d0/unused.nix"), "let a = 1; in 2").unwrap();
+    std::fs::write(inpd.join("d2/unused.nix"), "let b = 1; in 2").unwrap();
+    let (out1, out8) = (dir.join("out1"), dir.join("out8"));
+    let run1 = nix2js(&[
+        "--verbose".as_ref(),
+        "--jobs".as_ref(),
+        "1".as_ref(),
+        &inpd,
+        &out1,
+    ]);
+    let run8 = nix2js(&[
+        "--verbose".as_ref(),
+        "--jobs".as_ref(),
+        "8".as_ref(),
+        &inpd,
+        &out8,
+    ]);
+    // some fixtures are expected to fail
+    assert_eq!(run1.status.code(), Some(1));
+    assert_eq!(run1.status, run8.status);
+    assert_eq!(
+        String::from_utf8_lossy(&run1.stderr),
+        String::from_utf8_lossy(&run8.stderr)
+    );
+    let (tree1, tree8) = (read_tree(&out1), read_tree(&out8));
+    assert!(tree1.contains_key(Path::new("d0/unused.nix.js")));
+    assert!(tree1.contains_key(Path::new("d2/unused.nix.js.map")));
+    assert_eq!(tree1, tree8);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn output_extension() {
+    let dir = tmpdir("output-extension");
+    let (inpd, outd) = (dir.join("in"), dir.join("out"));
+    std::fs::create_dir_all(inpd.join("sub")).unwrap();
+    std::fs::write(inpd.join("a.nix"), "1").unwrap();
+    std::fs::write(inpd.join("sub/b.nix"), "2").unwrap();
+    std::fs::write(inpd.join("sub/c.txt"), "3").unwrap();
+    let run = nix2js(&["--output-extension".as_ref(), ".mjs".as_ref(), &inpd, &outd]);
+    assert!(run.status.success());
+    let tree = read_tree(&outd);
+    let names: Vec<_> = tree.keys().map(|i| i.to_str().unwrap()).collect();
+    assert_eq!(names, ["a.mjs", "a.mjs.map", "sub/b.mjs", "sub/b.mjs.map"]);
+    let js = String::from_utf8_lossy(&tree[Path::new("sub/b.mjs")]);
+    assert!(js.ends_with("\n//# sourceMappingURL=b.mjs.map"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn no_source_map() {
+    let dir = tmpdir("no-source-map");
+    let (inpf, outf, mapf) = (dir.join("a.nix"), dir.join("a.js"), dir.join("a.js.map"));
+    std::fs::write(&inpf, "let a = 1; in a").unwrap();
+    let run = nix2js(&["--no-source-map".as_ref(), &inpf, &outf, &mapf]);
+    assert!(run.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&run.stderr),
+        format!("--no-source-map given, not writing {}\n", mapf.display())
+    );
+    assert!(!mapf.exists());
+    let js = std::fs::read_to_string(&outf).unwrap();
+    assert!(!js.contains("sourceMappingURL"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn entry_export() {
+    let dir = tmpdir("entry-export");
+    let (inpf, outf) = (dir.join("a.nix"), dir.join("a.js"));
+    std::fs::write(&inpf, "let a = 1; in a").unwrap();
+    let run = nix2js(&["--entry-export".as_ref(), "evalNix".as_ref(), &inpf, &outf]);
+    assert!(run.status.success());
+    let js = std::fs::read_to_string(&outf).unwrap();
+    assert!(js.starts_with("export const evalNix=async function(nixRt,nixBlti){let nixOp="));
+    assert!(js.ends_with(";}"));
+    assert_eq!(js.matches("export ").count(), 1);
+
+    // rejected by the translator, nothing gets written
+    std::fs::remove_file(&outf).unwrap();
+    for name in ["eval-nix", "class"] {
+        let run = nix2js(&["--entry-export".as_ref(), name.as_ref(), &inpf, &outf]);
+        assert_eq!(
+            String::from_utf8_lossy(&run.stderr),
+            format!("invalid entry export name '{}'\n", name)
+        );
+        assert!(!outf.exists());
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn name_prefix() {
+    let dir = tmpdir("name-prefix");
+    let (inpf, outf) = (dir.join("a.nix"), dir.join("a.js"));
+    std::fs::write(&inpf, "let a = 1; in a").unwrap();
+    let run = nix2js(&["--name-prefix".as_ref(), "zz".as_ref(), &inpf, &outf]);
+    assert!(run.status.success());
+    assert!(std::fs::read_to_string(&outf).unwrap().contains("zz__a"));
+
+    std::fs::remove_file(&outf).unwrap();
+    let run = nix2js(&["--name-prefix".as_ref(), "1x".as_ref(), &inpf, &outf]);
+    assert_eq!(
+        String::from_utf8_lossy(&run.stderr),
+        "invalid name prefix '1x'\n"
+    );
+    assert!(!outf.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,17 @@
+//! helpers shared by the integration tests
+
+/// the fixtures in `docs/testset`, as `(path, source)`, sorted by path
+pub fn fixtures() -> Vec<(std::path::PathBuf, String)> {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/testset");
+    let mut ret: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|i| i.unwrap().path())
+        .filter(|i| i.extension().is_some_and(|e| e == "nix"))
+        .map(|i| {
+            let s = std::fs::read_to_string(&i).unwrap();
+            (i, s)
+        })
+        .collect();
+    ret.sort();
+    ret
+}
@@ -0,0 +1,80 @@
+//! evaluation tests, these require the `eval` feature (`cargo test --features eval`)
+#![cfg(feature = "eval")]
+
+mod common;
+
+use common::fixtures;
+use nix2js::{eval_pure, eval_pure_with_options, TranslateOptions};
+
+// fixtures whose expected error message comes from the JS engine,
+// only the outcome is compared for these
+const ENGINE_ERRORS: &[&str] = &["apply-non-function.nix"];
+
+// the `# expected:` header of a fixture is either a Nix expression, which gets
+// evaluated too, or `error: <message>` (abbreviated messages end in ` ...`);
+// fixtures which need I/O or the runtime (e.g. `import`) are skipped
+#[test]
+fn expected_headers() {
+    for (path, s) in fixtures() {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let Some(expected) = s
+            .trim_start_matches('\u{feff}')
+            .lines()
+            .find_map(|l| l.strip_prefix("# expected: "))
+        else {
+            continue;
+        };
+        let got = eval_pure(&s);
+        if let Err(errs) = &got {
+            if errs
+                .iter()
+                .any(|i| i.ends_with(" is not supported in pure evaluation"))
+            {
+                continue;
+            }
+        }
+        match expected.strip_prefix("error: ") {
+            Some(msg) => {
+                let errs = got.expect_err(name).join("\n");
+                let msg = msg.strip_suffix(" ...").unwrap_or(msg);
+                assert!(
+                    ENGINE_ERRORS.contains(&name) || errs.contains(msg),
+                    "{}: {}",
+                    name,
+                    errs
+                );
+            }
+            None => assert_eq!(got, eval_pure(expected), "{}", name),
+        }
+    }
+}
+
+#[test]
+fn minify_preserves_semantics() {
+    let opts = TranslateOptions {
+        minify: true,
+        ..Default::default()
+    };
+    let mut inputs: Vec<_> = fixtures().into_iter().map(|(_, s)| s).collect();
+    // inherit-from with multiple identifiers shares a temporary
+    inputs.push("let inherit ({ x = 1; y = 2; }) x y; in x + y".to_string());
+    inputs.push("rec { inherit ({ x = 1; y = 2; }) x y; z = x + y; }".to_string());
+    inputs.push("let a = 1; in assert a == 1; { inherit a; }".to_string());
+    for s in inputs {
+        assert_eq!(eval_pure_with_options(&s, &opts), eval_pure(&s), "{}", s);
+    }
+}
+
+#[test]
+fn name_prefix_preserves_semantics() {
+    let opts = TranslateOptions {
+        name_prefix: "zz".to_string(),
+        ..Default::default()
+    };
+    for (_, s) in fixtures() {
+        // JS errors (e.g. of builtins missing in the prelude) mention the prefix
+        if let Ok(expected) = eval_pure(&s) {
+            assert_eq!(eval_pure_with_options(&s, &opts), Ok(expected), "{}", s);
+        }
+    }
+}
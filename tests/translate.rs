@@ -0,0 +1,403 @@
+mod common;
+
+use common::fixtures;
+use nix2js::{translate_with_options, TranslateOptions};
+
+fn tr(s: &str, opts: &TranslateOptions) -> nix2js::TranslateOutput {
+    translate_with_options(s, "test.nix", opts).unwrap()
+}
+
+/// the segments of a source map as absolute `(gen_line, gen_col, src_line, src_col)`
+fn segments(map: &str) -> Vec<(usize, usize, usize, usize)> {
+    let map: serde_json::Value = serde_json::from_str(map).unwrap();
+    let mut ret = Vec::new();
+    let (mut src_line, mut src_col) = (0, 0);
+    for (gen_line, line) in map["mappings"].as_str().unwrap().split(';').enumerate() {
+        let mut gen_col = 0;
+        for seg in line.split(',').filter(|i| !i.is_empty()) {
+            let mut it = seg.bytes();
+            let mut fields = Vec::new();
+            while let Ok(x) = vlq::decode(&mut it) {
+                fields.push(x);
+            }
+            gen_col += fields[0];
+            if fields.len() >= 4 {
+                src_line += fields[2];
+                src_col += fields[3];
+                ret.push((
+                    gen_line,
+                    gen_col as usize,
+                    src_line as usize,
+                    src_col as usize,
+                ));
+            }
+        }
+    }
+    ret
+}
+
+/// the (line, column) of the first occurence of `pat` in `s`
+fn position_of(s: &str, pat: &str) -> (usize, usize) {
+    let idx = s.find(pat).unwrap();
+    let line_start = s[..idx].rfind('\n').map_or(0, |i| i + 1);
+    (s[..idx].matches('\n').count(), idx - line_start)
+}
+
+#[test]
+fn minify() {
+    let opts = TranslateOptions {
+        minify: true,
+        ..Default::default()
+    };
+    let (mut full, mut min) = (0, 0);
+    for (path, s) in fixtures() {
+        let (Ok(a), Ok(b)) = (
+            translate_with_options(&s, "test.nix", &Default::default()),
+            translate_with_options(&s, "test.nix", &opts),
+        ) else {
+            continue;
+        };
+        // keywords must stay separated from the following identifier
+        for kw in ["{const", ";const", ";return", "{return"] {
+            for (idx, _) in b.js.match_indices(kw) {
+                let next = b.js[idx + kw.len()..].chars().next();
+                assert!(
+                    !next.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_'),
+                    "{}: `{}` glued to an identifier",
+                    path.display(),
+                    kw
+                );
+            }
+        }
+        full += a.js.len();
+        min += b.js.len();
+    }
+    assert!(min < full, "{} >= {}", min, full);
+
+    // inherit-from with multiple identifiers shares a temporary
+    let js = tr("let inherit ({ x = 1; y = 2; }) x y; in x + y", &opts).js;
+    assert!(js.contains("const r0="), "{}", js);
+}
+
+#[test]
+fn cache_assert_positions() {
+    let mut cache = nix2js::TranslateCache::new(TranslateOptions {
+        no_source_map: true,
+        ..Default::default()
+    });
+    let a = cache.translate("assert true && true; 1", "a.nix").unwrap();
+    // same structure, but the assert is on another line
+    let b = cache
+        .translate("\n\nassert true && true;\n1\n", "b.nix")
+        .unwrap();
+    // same structure, but with a comment inside of the condition
+    let c = cache
+        .translate("assert true && /* x */ true; 1", "c.nix")
+        .unwrap();
+    assert_eq!(cache.hits(), 0);
+    assert!(a.js.contains("\"line 0: true && true\""), "{}", a.js);
+    assert!(b.js.contains("\"line 2: true && true\""), "{}", b.js);
+    assert!(
+        c.js.contains("\"line 0: true && /* x */ true\""),
+        "{}",
+        c.js
+    );
+
+    // reformatted outside of the assert condition
+    let d = cache
+        .translate("assert true && true;   1 # one", "d.nix")
+        .unwrap();
+    assert_eq!(cache.hits(), 1);
+    assert_eq!(d.js, a.js);
+}
+
+#[test]
+fn cache_warnings() {
+    let mut cache = nix2js::TranslateCache::new(TranslateOptions {
+        no_source_map: true,
+        output_ratio_warn: Some(12.0),
+        ..Default::default()
+    });
+    // differing only in leading newlines, the warnings must refer to the right line
+    let a = cache.translate("let a = 1; in 2", "a.nix").unwrap();
+    let b = cache.translate("\n\nlet a = 1; in 2", "b.nix").unwrap();
+    assert_eq!(a.warnings, ["line 0: unused let binding a"]);
+    assert_eq!(b.warnings, ["line 2: unused let binding a"]);
+    assert_eq!(b.stats.input_bytes, 17);
+    assert_eq!(a.js, b.js);
+
+    // the output ratio depends on the length of the input
+    let c = cache.translate("{ a = 1; }", "c.nix").unwrap();
+    let d_src = "{ a = 1; } # a long comment, which makes the input larger";
+    let d = cache.translate(d_src, "d.nix").unwrap();
+    let e = cache.translate("{a=1;}", "e.nix").unwrap();
+    assert_eq!(cache.hits(), 2);
+    assert_eq!(c.warnings.len(), 1, "{:?}", c.warnings);
+    assert!(d.warnings.is_empty(), "{:?}", d.warnings);
+    assert_eq!(d.stats.input_bytes, d_src.len());
+    assert_eq!(
+        e.warnings,
+        [format!(
+            "generated output ({} bytes) exceeds 12x the input size (6 bytes)",
+            e.js.len()
+        )]
+    );
+}
+
+#[test]
+fn name_prefix() {
+    let src = "let f = { a, ... }@args: with args; [ a b ]; in rec { x = f { a = 1; b = 2; }; inherit (x) y; }";
+    let dfl = tr(src, &Default::default()).js;
+    let opts = TranslateOptions {
+        name_prefix: "zz".to_string(),
+        ..Default::default()
+    };
+    let js = tr(src, &opts).js;
+    // every generated `nix...` identifier gets the prefix, except the parameters
+    let stripped = js
+        .replace("nixBlti.nixOp", "")
+        .replace("nixBlti", "")
+        .replace("nixRt", "");
+    assert!(!stripped.contains("nix"), "{}", js);
+    assert_eq!(js.replace("zz", "nix"), dfl);
+
+    for pfx in ["", "1x", "a-b"] {
+        let opts = TranslateOptions {
+            name_prefix: pfx.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            translate_with_options("1", "test.nix", &opts).unwrap_err(),
+            [format!("invalid name prefix '{}'", pfx)]
+        );
+    }
+}
+
+#[test]
+fn translate_bytes() {
+    let opts = TranslateOptions::default();
+    for (path, s) in fixtures() {
+        let Ok(out) = translate_with_options(&s, "test.nix", &opts) else {
+            continue;
+        };
+        let (js, map) = nix2js::translate_bytes(s.as_bytes(), "test.nix", &opts).unwrap();
+        assert_eq!(js, out.js.into_bytes(), "{}", path.display());
+        assert_eq!(map, out.map.into_bytes(), "{}", path.display());
+    }
+}
+
+#[test]
+fn translate_lossy() {
+    let opts = TranslateOptions::default();
+    let s = b"let a = 1;\n  b = \"x\xffy\"; in b";
+    assert_eq!(
+        nix2js::translate_bytes(s, "test.nix", &opts),
+        Err(vec![
+            "test.nix: invalid utf-8 sequence of 1 bytes from index 19".to_string()
+        ])
+    );
+
+    let out = nix2js::translate_lossy(s, "test.nix", &opts).unwrap();
+    assert_eq!(
+        out.warnings,
+        [
+            "line 1: input contains invalid UTF-8 \
+             (invalid utf-8 sequence of 1 bytes from index 19), replaced with U+FFFD",
+            "line 0: unused let binding a",
+        ]
+    );
+    assert!(out.js.contains("x\u{fffd}y"));
+    assert_eq!(out.stats.input_bytes, s.len() + 2);
+    assert_eq!(
+        out.js,
+        tr("let a = 1;\n  b = \"x\u{fffd}y\"; in b", &opts).js
+    );
+
+    let out = nix2js::translate_lossy(b"let a = 1; in a", "test.nix", &opts).unwrap();
+    assert!(out.warnings.is_empty());
+}
+
+#[test]
+fn banner() {
+    let src = "# comment\n  4242";
+    let plain = tr(src, &Default::default());
+    let out = tr(
+        src,
+        &TranslateOptions {
+            banner: Some("SPDX-License-Identifier: MIT\n\n(c) someone".to_string()),
+            ..Default::default()
+        },
+    );
+    assert!(out
+        .js
+        .starts_with("// SPDX-License-Identifier: MIT\n//\n// (c) someone\n"));
+    assert_eq!(out.js[out.js.len() - plain.js.len()..], plain.js);
+
+    // the literal on Nix line 1 (0-based) must still be found after the offset
+    let (gen_line, gen_col) = position_of(&out.js, "4242");
+    assert_eq!(gen_line, position_of(&plain.js, "4242").0 + 3);
+    assert!(segments(&out.map).contains(&(gen_line, gen_col, 1, 2)));
+    assert_eq!(
+        segments(&out.map)
+            .into_iter()
+            .map(|(l, c, sl, sc)| (l - 3, c, sl, sc))
+            .collect::<Vec<_>>(),
+        segments(&plain.map)
+    );
+}
+
+#[test]
+fn no_source_map() {
+    let opts = TranslateOptions {
+        no_source_map: true,
+        ..Default::default()
+    };
+    for (path, s) in fixtures() {
+        let (Ok(a), Ok(b)) = (
+            translate_with_options(&s, "test.nix", &Default::default()),
+            translate_with_options(&s, "test.nix", &opts),
+        ) else {
+            continue;
+        };
+        assert_eq!(a.js, b.js, "{}", path.display());
+        assert!(b.map.is_empty(), "{}", path.display());
+        assert_eq!(b.stats.map_segments, 0, "{}", path.display());
+        assert_eq!(b.stats.distinct_names, 0, "{}", path.display());
+        assert_eq!(a.stats.node_count, b.stats.node_count, "{}", path.display());
+    }
+}
+
+#[test]
+fn omit_preamble() {
+    let opts = TranslateOptions {
+        omit_preamble: true,
+        ..Default::default()
+    };
+    let out = tr("let a = 1; in a", &opts);
+    assert!(!out.js.contains("initRtDep") && !out.js.contains("mkScopeWith"));
+    assert!(out.js.starts_with("nixBlti.PLazy.from(") && out.js.ends_with(')'));
+
+    // the default output merely wraps the bare expression
+    for (path, s) in fixtures() {
+        let (Ok(a), Ok(b)) = (
+            translate_with_options(&s, "test.nix", &Default::default()),
+            translate_with_options(&s, "test.nix", &opts),
+        ) else {
+            continue;
+        };
+        let expected = format!("{}return {};", nix2js::runtime_preamble(), b.js);
+        assert_eq!(a.js, expected, "{}", path.display());
+    }
+}
+
+#[test]
+fn translate_inline() {
+    const PREFIX: &str = "\n//# sourceMappingURL=data:application/json;charset=utf-8;base64,";
+    let opts = TranslateOptions::default();
+    let src = "let a = 1; in a";
+    let plain = tr(src, &opts);
+    let out = nix2js::translate_inline(src, "test.nix", &opts).unwrap();
+    let (js, data) = out.js.split_once(PREFIX).unwrap();
+    assert_eq!(js, plain.js);
+    let map = String::from_utf8(base64::decode(data).unwrap()).unwrap();
+    assert_eq!(map, out.map);
+    let map: serde_json::Value = serde_json::from_str(&map).unwrap();
+    assert_eq!(map["version"], 3);
+    assert_eq!(map["sources"], serde_json::json!(["test.nix"]));
+    assert!(!segments(&out.map).is_empty());
+
+    let opts = TranslateOptions {
+        no_source_map: true,
+        ..Default::default()
+    };
+    let out = nix2js::translate_inline(src, "test.nix", &opts).unwrap();
+    assert!(!out.js.contains("sourceMappingURL"));
+}
+
+#[test]
+fn jsdoc() {
+    let src = "let a = 1;\nin a";
+    let with_export = |jsdoc| TranslateOptions {
+        entry_export: Some("evalNix".to_string()),
+        jsdoc,
+        ..Default::default()
+    };
+    let plain = tr(src, &with_export(false));
+    let out = tr(src, &with_export(true));
+    assert!(!plain.js.contains("/**"));
+    let (doc, rest) = out.js.split_once("*/\n").unwrap();
+    assert!(doc.starts_with("/**\n") && doc.contains("@param {object} nixRt"));
+    assert_eq!(rest, plain.js);
+
+    // only the lines get shifted, the columns stay the same
+    let doc_lines = doc.matches('\n').count() + 1;
+    assert_eq!(
+        segments(&out.map)
+            .into_iter()
+            .map(|(l, c, sl, sc)| (l - doc_lines, c, sl, sc))
+            .collect::<Vec<_>>(),
+        segments(&plain.map)
+    );
+    // the reference to `a` on Nix line 1
+    let gen_pos = position_of(&out.js, "(await nix__a)");
+    assert!(segments(&out.map).contains(&(gen_pos.0, gen_pos.1, 1, 3)));
+
+    // ignored without `entry_export`
+    let opts = TranslateOptions {
+        jsdoc: true,
+        ..Default::default()
+    };
+    assert_eq!(tr(src, &opts).js, tr(src, &Default::default()).js);
+}
+
+#[test]
+fn folded_builtins() {
+    // enables every fold
+    let opts = TranslateOptions {
+        store_dir: Some("/nix/store".to_string()),
+        nix_version: Some("2.18.0".to_string()),
+        lang_version: Some(6),
+        trace_mode: nix2js::TraceMode::Suppress,
+        omit_preamble: true,
+        ..Default::default()
+    };
+    for i in nix2js::list_builtins() {
+        let src = match i.name {
+            // only folded if applied to a literal
+            "fromTOML" => "builtins.fromTOML \"a = 1\"".to_string(),
+            _ => format!("builtins.{}", i.name),
+        };
+        let js = tr(&src, &opts).js;
+        assert_eq!(!js.contains("nixBltiRT"), i.folded, "{}: {}", src, js);
+    }
+}
+
+#[test]
+fn entry_export_names() {
+    for (name, valid) in [
+        ("evalNix", true),
+        ("_$1", true),
+        ("", false),
+        ("1a", false),
+        ("a-b", false),
+        ("class", false),
+        ("await", false),
+    ] {
+        let opts = TranslateOptions {
+            entry_export: Some(name.to_string()),
+            ..Default::default()
+        };
+        let res = translate_with_options("1", "test.nix", &opts);
+        if valid {
+            assert!(res
+                .unwrap()
+                .js
+                .starts_with(&format!("export const {}=", name)));
+        } else {
+            assert_eq!(
+                res.unwrap_err(),
+                [format!("invalid entry export name '{}'", name)]
+            );
+        }
+    }
+}